@@ -0,0 +1,129 @@
+#version 460
+
+layout(location = 0) out vec4 f_color;
+
+layout(location = 0) in vec4 i_pos;
+layout(location = 1) flat in uint i_face;
+layout(location = 2) flat in uint i_material;
+layout(location = 3) in float i_ao;
+layout(location = 4) in vec3 i_normal;
+layout(location = 5) in vec3 i_world_pos;
+layout(location = 6) in float i_voxel_light;
+layout(location = 7) in float i_view_dist;
+
+struct PointLight {
+    vec4 pos_radius;
+    vec4 color;
+};
+
+// see lights::Lights::get_ubo_data
+layout(set = 0, binding = 1) uniform Lights {
+    vec4 lamp_pos; // w: enabled
+    vec4 lamp_dir; // w: range
+    vec4 lamp_color; // w: ambient
+    vec4 lamp_cone; // x: cos of inner angle, y: cos of outer angle
+    PointLight point_lights[32];
+    uint point_light_count;
+};
+
+// see fog::Fog::get_ubo_data
+layout(set = 0, binding = 2) uniform Fog {
+    vec4 fog_color; // w: mode, 0 for linear and 1 for exponential
+    vec4 fog_params; // x: start, y: end, z: density
+};
+
+// per chunk, see debug::DebugView::push_constants
+layout(push_constant) uniform DebugParams {
+    uint debug_mode; // 0: shaded, 1: lod, 2: normals
+    uint lod;
+    uint culled;
+};
+
+// the image is flipped vertically, so -Y is up on screen
+const vec3 LIGHT_DIR = normalize(vec3(0.4, -1.0, 0.3));
+const vec3 TORCH_COLOR = vec3(1.0, 0.75, 0.45);
+
+// same as debug::LOD_COLORS, indexed by lod_rank
+const vec3 LOD_COLORS[4] = vec3[4](
+    vec3(0.2, 0.9, 0.2),
+    vec3(0.9, 0.9, 0.2),
+    vec3(0.9, 0.5, 0.1),
+    vec3(0.9, 0.2, 0.2)
+);
+
+// lods are 0, 2, 4, 8, same as debug::lod_rank
+uint lod_rank(uint lod) {
+    return lod == 0u ? 0u : min(uint(findLSB(lod)), 3u);
+}
+
+float fog_factor(float dist) {
+    float start = fog_params.x;
+    float end = fog_params.y;
+
+    float f;
+    if (fog_color.w < 0.5) {
+        f = clamp((dist - start) / max(end - start, 0.0001), 0.0, 1.0);
+    } else {
+        f = 1.0 - exp(-fog_params.z * max(dist - start, 0.0));
+    }
+
+    // whatever the mode, the far edge of the loaded region is fully fogged
+    return max(f, smoothstep(mix(start, end, 0.75), end, dist));
+}
+
+float attenuation(float dist, float range) {
+    float a = clamp(1.0 - dist / range, 0.0, 1.0);
+    return a * a;
+}
+
+void main() {
+    float r = 0.5 + 0.5 * sin(i_pos.x * 0.2 + i_pos.y * 0.2);
+    float g = 0.5 + 0.5 * cos(i_pos.y * 0.2 + i_pos.z * 0.2);
+    float b = 0.5 + 0.5 * sin(i_pos.z * 0.2 + i_pos.x * 0.2);
+    vec3 albedo = vec3(r, g, b);
+
+    vec3 normal = normalize(i_normal);
+    float ao = mix(0.3, 1.0, i_ao);
+    float diffuse = max(dot(normal, LIGHT_DIR), 0.0);
+
+    vec3 light = vec3(lamp_color.w * (0.5 + 0.5 * diffuse));
+
+    /* baked voxel light, squared so it falls off faster than linearly */
+    light += TORCH_COLOR * i_voxel_light * i_voxel_light;
+
+    /* headlamp */
+    if (lamp_pos.w > 0.0) {
+        vec3 to_frag = i_world_pos - lamp_pos.xyz;
+        float dist = length(to_frag);
+        vec3 dir = to_frag / max(dist, 0.0001);
+
+        float spot = smoothstep(lamp_cone.y, lamp_cone.x, dot(dir, normalize(lamp_dir.xyz)));
+        float lambert = max(dot(normal, -dir), 0.0);
+
+        light += lamp_color.rgb * lambert * spot * attenuation(dist, lamp_dir.w);
+    }
+
+    /* point lights */
+    for (uint i = 0; i < point_light_count; i++) {
+        vec3 to_light = point_lights[i].pos_radius.xyz - i_world_pos;
+        float dist = length(to_light);
+        float lambert = max(dot(normal, to_light / max(dist, 0.0001)), 0.0);
+
+        light += point_lights[i].color.rgb * lambert * attenuation(dist, point_lights[i].pos_radius.w);
+    }
+
+    vec3 color = albedo * light * ao;
+    color = mix(color, fog_color.rgb, fog_factor(i_view_dist));
+
+    if (debug_mode == 1) {
+        color = LOD_COLORS[lod_rank(lod)] * ao * (0.5 + 0.5 * diffuse);
+    } else if (debug_mode == 2) {
+        color = normal * 0.5 + 0.5;
+    }
+
+    if (culled != 0) {
+        color = mix(color, vec3(1.0, 0.0, 0.0), 0.6);
+    }
+
+    f_color = vec4(color, 1.0);
+}
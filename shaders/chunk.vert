@@ -0,0 +1,51 @@
+#version 460
+
+layout(location = 0) in uint data; // per vertex, packed
+layout(location = 2) in uint light; // per vertex, baked voxel light
+
+layout (location = 1) in vec3 ofs; // per instance
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+};
+
+layout(location = 0) out vec4 o_pos;
+layout(location = 1) flat out uint o_face;
+layout(location = 2) flat out uint o_material;
+layout(location = 3) out float o_ao;
+layout(location = 4) out vec3 o_normal;
+layout(location = 5) out vec3 o_world_pos;
+layout(location = 6) out float o_voxel_light;
+layout(location = 7) out float o_view_dist;
+
+// same order as voxel_gen::FACE_NORMALS
+const vec3 NORMALS[6] = vec3[6](
+    vec3(-1.0, 0.0, 0.0),
+    vec3(1.0, 0.0, 0.0),
+    vec3(0.0, -1.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, -1.0),
+    vec3(0.0, 0.0, 1.0)
+);
+
+void main() {
+    vec3 pos = vec3(
+        float(data & 0x7Fu),
+        float((data >> 7) & 0x7Fu),
+        float((data >> 14) & 0x7Fu)
+    );
+
+    o_face = (data >> 21) & 0x7u;
+    o_ao = float((data >> 24) & 0x3u) / 3.0;
+    o_material = data >> 26;
+    o_normal = NORMALS[o_face];
+    o_voxel_light = float(light & 0xFu) / 15.0;
+
+    vec4 view_pos = view * vec4(pos + ofs, 1.0);
+    gl_Position = proj * view_pos;
+
+    o_pos = vec4(pos, 1.0);
+    o_world_pos = pos + ofs;
+    o_view_dist = length(view_pos.xyz);
+}
@@ -0,0 +1,40 @@
+#version 460
+
+layout(location = 0) out vec4 f_color;
+
+layout(location = 0) in vec3 i_color;
+layout(location = 1) in vec3 i_world_pos;
+layout(location = 2) in float i_view_dist;
+
+// see fog::Fog::get_ubo_data
+layout(set = 0, binding = 2) uniform Fog {
+    vec4 fog_color; // w: mode, 0 for linear and 1 for exponential
+    vec4 fog_params; // x: start, y: end, z: density
+};
+
+const vec3 LIGHT_DIR = normalize(vec3(0.4, -1.0, 0.3));
+
+// same as in chunk.frag
+float fog_factor(float dist) {
+    float start = fog_params.x;
+    float end = fog_params.y;
+
+    float f;
+    if (fog_color.w < 0.5) {
+        f = clamp((dist - start) / max(end - start, 0.0001), 0.0, 1.0);
+    } else {
+        f = 1.0 - exp(-fog_params.z * max(dist - start, 0.0));
+    }
+
+    return max(f, smoothstep(mix(start, end, 0.75), end, dist));
+}
+
+void main() {
+    // flat normal of the triangle, the prototypes don't carry normals
+    vec3 normal = normalize(cross(dFdx(i_world_pos), dFdy(i_world_pos)));
+    float diffuse = abs(dot(normal, LIGHT_DIR));
+
+    vec3 color = i_color * (0.35 + 0.65 * diffuse);
+
+    f_color = vec4(mix(color, fog_color.rgb, fog_factor(i_view_dist)), 1.0);
+}
@@ -0,0 +1,27 @@
+#version 460
+
+layout(location = 0) in vec3 pos; // per vertex
+
+// per instance, see vertex::PrototypeInstance
+layout(location = 1) in vec3 ofs;
+layout(location = 2) in float scale;
+layout(location = 3) in vec3 color;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+};
+
+layout(location = 0) out vec3 o_color;
+layout(location = 1) out vec3 o_world_pos;
+layout(location = 2) out float o_view_dist;
+
+void main() {
+    vec3 world_pos = pos * scale + ofs;
+    vec4 view_pos = view * vec4(world_pos, 1.0);
+    gl_Position = proj * view_pos;
+
+    o_color = color;
+    o_world_pos = world_pos;
+    o_view_dist = length(view_pos.xyz);
+}
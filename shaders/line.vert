@@ -0,0 +1,19 @@
+#version 460
+
+layout(location = 0) in vec3 pos; // per vertex
+layout(location = 1) in vec3 color; // per vertex
+
+layout (location = 2) in vec3 ofs; // per instance
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+};
+
+layout(location = 0) out vec3 o_color;
+
+void main() {
+    gl_Position = proj * view * vec4(pos + ofs, 1.0);
+
+    o_color = color;
+}
@@ -0,0 +1,13 @@
+#version 460
+
+layout(location = 0) out vec4 f_color;
+
+layout(location = 0) in vec4 i_pos;
+
+void main() {
+    float r = 0.5 + 0.5 * sin(i_pos.x * 0.2 + i_pos.y * 0.2);
+    float g = 0.5 + 0.5 * cos(i_pos.y * 0.2 + i_pos.z * 0.2);
+    float b = 0.5 + 0.5 * sin(i_pos.z * 0.2 + i_pos.x * 0.2);
+
+    f_color = vec4(r, g, b, 1.0);
+}
@@ -0,0 +1,22 @@
+#version 460
+
+layout(location = 0) in vec3 pos; // per vertex
+
+layout (location = 1) in vec3 ofs; // per instance
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+};
+
+layout(set = 1, binding = 0) uniform Model {
+    mat4 model;
+};
+
+layout(location = 0) out vec4 o_pos;
+
+void main() {
+    gl_Position = proj * view * model * vec4(pos + ofs, 1.0);
+
+    o_pos = vec4(pos, 1.0);
+}
@@ -0,0 +1,269 @@
+use bevy_ecs::system::Resource;
+use glam::{ivec3, vec3, IVec3, Vec3};
+use imgui::Ui;
+use noise::{NoiseFn, Perlin};
+
+use crate::world::{ChunkWorld, Voxel};
+
+/// Brushes bigger than this would stall the frame re-meshing
+pub const MAX_RADIUS: f32 = 32.0;
+
+/// How much bigger the features of the noise brush are than a voxel
+const NOISE_SCALE: f64 = 0.25;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrushShape {
+    Sphere,
+    Box,
+    /// upright, as tall as it is wide
+    Cylinder,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrushOp {
+    /// fills air with the material
+    Add,
+    /// turns everything into air
+    Subtract,
+    /// changes the material of solid voxels, leaving air alone
+    Replace,
+    /// fills voxels mostly surrounded by solid ones and empties those mostly
+    /// surrounded by air, which rounds off edges and fills small holes
+    Smooth,
+    /// adds and removes voxels on surfaces following a noise
+    Noise,
+}
+
+/*
+A shaped operation on the voxels of the world. Strokes are worked out first
+and applied after, so smoothing doesn't read voxels it already changed.
+
+`falloff` is the part of the radius, from the outside in, where the brush
+gets weaker: 0 gives hard edges, 1 fades from the center out. Weaker means
+fewer voxels get changed, picked by a hash of their position so the same
+stroke always gives the same result.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub op: BrushOp,
+    pub radius: f32,
+    pub falloff: f32,
+    /// what `Add` and `Replace` put down
+    pub material: usize,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            shape: BrushShape::Sphere,
+            op: BrushOp::Subtract,
+            radius: 4.0,
+            falloff: 0.0,
+            material: 1,
+        }
+    }
+}
+
+impl Brush {
+    /// The radius the brush works with, whatever was asked for
+    fn radius(&self) -> f32 {
+        self.radius.clamp(0.5, MAX_RADIUS)
+    }
+
+    /// Distance of the center of voxel `pos` from `center`, 1 being the edge of the shape
+    fn distance(&self, center: Vec3, pos: IVec3) -> f32 {
+        let d = (pos.as_vec3() + 0.5 - center).abs() / self.radius();
+
+        match self.shape {
+            BrushShape::Sphere => d.length(),
+            BrushShape::Box => d.max_element(),
+            BrushShape::Cylinder => vec3(d.x, 0.0, d.z).length().max(d.y),
+        }
+    }
+
+    /// How strongly the brush acts on a voxel, from 0 to 1
+    fn weight(&self, center: Vec3, pos: IVec3) -> f32 {
+        let d = self.distance(center, pos);
+        if d > 1.0 {
+            return 0.0;
+        }
+
+        let falloff = self.falloff.clamp(0.0, 1.0);
+        if d <= 1.0 - falloff {
+            return 1.0;
+        }
+
+        (1.0 - d) / falloff
+    }
+
+    /// Voxels to change for a stroke at `center`, with the voxel they become.
+    /// `get` is `None` for voxels that aren't loaded, those are left alone
+    pub fn stroke(&self, center: Vec3, get: &impl Fn(IVec3) -> Option<Voxel>) -> Vec<(IVec3, Voxel)> {
+        let radius = self.radius();
+        let min = (center - radius).floor().as_ivec3();
+        let max = (center + radius).ceil().as_ivec3();
+
+        let perlin = Perlin::new(1);
+        let solid = |pos: IVec3| get(pos).map_or(false, |voxel| voxel.id != 0);
+        let mut changes = vec![];
+
+        for x in min.x..max.x {
+            for y in min.y..max.y {
+                for z in min.z..max.z {
+                    let pos = ivec3(x, y, z);
+                    let weight = self.weight(center, pos);
+                    if weight <= 0.0 || weight < hash01(pos) {
+                        continue;
+                    }
+                    let Some(voxel) = get(pos) else {
+                        continue;
+                    };
+
+                    let new = match self.op {
+                        BrushOp::Add => if voxel.id == 0 { self.material } else { voxel.id },
+                        BrushOp::Subtract => 0,
+                        BrushOp::Replace => if voxel.id != 0 { self.material } else { 0 },
+                        BrushOp::Smooth => {
+                            let solid_around = neighbourhood(pos).filter(|p| solid(*p)).count();
+                            match (voxel.id, solid_around) {
+                                (0, n) if n > 18 => self.material,
+                                (_, n) if n < 8 => 0,
+                                (id, _) => id,
+                            }
+                        }
+                        BrushOp::Noise => {
+                            let on_surface = neighbourhood(pos).any(|p| solid(p) != (voxel.id != 0));
+                            if !on_surface {
+                                voxel.id
+                            } else {
+                                let p = pos.as_dvec3() * NOISE_SCALE;
+                                let n = perlin.get([p.x, p.y, p.z]);
+                                match voxel.id {
+                                    0 if n > 0.2 => self.material,
+                                    _ if n < -0.2 => 0,
+                                    id => id,
+                                }
+                            }
+                        }
+                    };
+
+                    if new != voxel.id {
+                        changes.push((pos, Voxel { id: new }));
+                    }
+                }
+            }
+        }
+
+        changes
+    }
+
+    pub fn at(self, center: Vec3) -> BrushStroke {
+        BrushStroke { brush: self, center }
+    }
+
+    /// Strokes the world, returns how many voxels changed
+    pub fn apply(&self, world: &mut ChunkWorld, center: Vec3) -> usize {
+        let changes = self.stroke(center, &|pos| world.voxel_at(pos));
+        for (pos, voxel) in &changes {
+            world.set_voxel(*pos, *voxel);
+        }
+
+        changes.len()
+    }
+}
+
+/// A brush applied somewhere, queued by Lua
+#[derive(Clone, Copy, Debug)]
+pub struct BrushStroke {
+    pub brush: Brush,
+    pub center: Vec3,
+}
+
+/// The 26 voxels around `pos`
+fn neighbourhood(pos: IVec3) -> impl Iterator<Item = IVec3> {
+    (-1..=1)
+        .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| ivec3(x, y, z))))
+        .filter(|ofs| *ofs != IVec3::ZERO)
+        .map(move |ofs| pos + ofs)
+}
+
+/// Pseudo random number from 0 to 1 that only depends on `pos`
+fn hash01(pos: IVec3) -> f32 {
+    let mut h = (pos.x as u32).wrapping_mul(0x8da6b343)
+        ^ (pos.y as u32).wrapping_mul(0xd8163841)
+        ^ (pos.z as u32).wrapping_mul(0xcb1ab31f);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b3c6d);
+    h ^= h >> 12;
+
+    (h & 0xffffff) as f32 / 0xffffff as f32
+}
+
+/// The brush used by the mouse and the brushes window
+#[derive(Resource, Default)]
+pub struct Brushes {
+    pub current: Brush,
+    pub show_ui: bool,
+    /// set by the "apply at crosshair" button, picked up by the main loop
+    pub apply_requested: bool,
+}
+
+impl Brushes {
+    pub fn ui(&mut self, ui: &Ui) {
+        if !self.show_ui {
+            return;
+        }
+
+        let brush = &mut self.current;
+        ui.window("brushes").build(|| {
+            ui.text("shape");
+            ui.radio_button("sphere", &mut brush.shape, BrushShape::Sphere);
+            ui.same_line();
+            ui.radio_button("box", &mut brush.shape, BrushShape::Box);
+            ui.same_line();
+            ui.radio_button("cylinder", &mut brush.shape, BrushShape::Cylinder);
+
+            ui.text("operation");
+            ui.radio_button("add", &mut brush.op, BrushOp::Add);
+            ui.same_line();
+            ui.radio_button("subtract", &mut brush.op, BrushOp::Subtract);
+            ui.same_line();
+            ui.radio_button("replace", &mut brush.op, BrushOp::Replace);
+            ui.radio_button("smooth", &mut brush.op, BrushOp::Smooth);
+            ui.same_line();
+            ui.radio_button("noise", &mut brush.op, BrushOp::Noise);
+
+            ui.slider("radius", 0.5, MAX_RADIUS, &mut brush.radius);
+            ui.slider("falloff", 0.0, 1.0, &mut brush.falloff);
+            ui.slider("material", 1, 2, &mut brush.material);
+
+            if ui.button("apply at crosshair") {
+                self.apply_requested = true;
+            }
+            ui.text("or press the brush button (middle mouse)");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec3, IVec3};
+
+    use super::{Brush, MAX_RADIUS};
+    use crate::world::Voxel;
+
+    #[test]
+    fn huge_radii_still_carve_a_sphere() {
+        let brush = Brush { radius: 100.0, ..Default::default() };
+        let center = vec3(0.5, 0.5, 0.5);
+        let changes = brush.stroke(center, &|_: IVec3| Some(Voxel { id: 1 }));
+
+        assert!(!changes.is_empty());
+        assert!(changes.iter().all(|(pos, _)| (pos.as_vec3() + 0.5 - center).length() <= MAX_RADIUS));
+
+        /* a sphere is about half of its bounding cube */
+        let cube = (2.0 * MAX_RADIUS).powi(3);
+        assert!((changes.len() as f32) < cube * 0.6, "{} of {cube}", changes.len());
+    }
+}
@@ -0,0 +1,334 @@
+use std::{collections::VecDeque, fs, io, path::Path};
+
+use chaos_vk::graphics::camera::Camera;
+use glam::{vec3, Vec3};
+
+/// Where F9 saves the recording and F10 plays it from
+pub const CAMERA_PATH_FILE: &str = "camera_path.txt";
+
+/// How often a keyframe is taken while recording, in seconds
+const RECORD_INTERVAL: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    /// seconds since the start of the recording
+    pub time: f32,
+    pub pos: Vec3,
+    pub front: Vec3,
+}
+
+/*
+A recorded fly-through. Saved as text, one keyframe per line:
+
+    time pos.x pos.y pos.z front.x front.y front.z
+
+Played back with a fixed timestep (see `--headless --path`), the camera goes
+through exactly the same positions every run, so frame times and chunk
+streaming can be compared between commits.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let src = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+
+        let keyframes = src
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(line_i, line)| {
+                let values = line
+                    .split_whitespace()
+                    .map(str::parse::<f32>)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("{}:{}: {e}", path.display(), line_i + 1))?;
+
+                match values[..] {
+                    [time, px, py, pz, fx, fy, fz] => Ok(Keyframe {
+                        time,
+                        pos: vec3(px, py, pz),
+                        front: vec3(fx, fy, fz),
+                    }),
+                    _ => Err(format!("{}:{}: expected 7 numbers", path.display(), line_i + 1)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { keyframes })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let out: String = self.keyframes
+            .iter()
+            .map(|k| format!("{} {} {} {} {} {} {}\n", k.time, k.pos.x, k.pos.y, k.pos.z, k.front.x, k.front.y, k.front.z))
+            .collect();
+
+        fs::write(path, out)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    /// Position and direction at `time`. The position follows a Catmull-Rom
+    /// spline through the keyframes so the motion doesn't jerk at each of them
+    pub fn sample(&self, time: f32) -> Option<(Vec3, Vec3)> {
+        let frames = &self.keyframes;
+        let last = frames.len().checked_sub(1)?;
+
+        let i = frames.partition_point(|k| k.time <= time);
+        if i == 0 {
+            return Some((frames[0].pos, frames[0].front));
+        }
+        if i > last {
+            return Some((frames[last].pos, frames[last].front));
+        }
+
+        let (k1, k2) = (&frames[i - 1], &frames[i]);
+        let p0 = frames[i.saturating_sub(2)].pos;
+        let p3 = frames[(i + 1).min(last)].pos;
+
+        let t = ((time - k1.time) / (k2.time - k1.time).max(f32::EPSILON)).clamp(0.0, 1.0);
+        let pos = catmull_rom(p0, k1.pos, k2.pos, p3, t);
+        let front = k1.front.lerp(k2.front, t).normalize_or(k2.front);
+
+        Some((pos, front))
+    }
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    0.5 * (
+        2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3
+    )
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum PathState {
+    #[default]
+    Idle,
+    Recording {
+        path: CameraPath,
+        time: f32,
+        /// since the last keyframe
+        since_keyframe: f32,
+    },
+    Playing {
+        path: CameraPath,
+        time: f32,
+    },
+}
+
+impl PathState {
+    pub fn start_recording(&mut self) {
+        *self = PathState::Recording {
+            path: CameraPath::default(),
+            time: 0.0,
+            since_keyframe: RECORD_INTERVAL,
+        };
+    }
+
+    /// Returns what got recorded, if anything was
+    pub fn stop_recording(&mut self) -> Option<CameraPath> {
+        match std::mem::take(self) {
+            PathState::Recording { path, .. } => Some(path),
+            other => {
+                *self = other;
+                None
+            }
+        }
+    }
+
+    pub fn play(&mut self, path: CameraPath) {
+        *self = PathState::Playing { path, time: 0.0 };
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self, PathState::Playing { .. })
+    }
+
+    /// Takes a keyframe of `camera` when recording. When playing, returns
+    /// where the camera should be, or `None` once the path is over
+    pub fn advance(&mut self, dt: f32, camera: &Camera) -> Option<(Vec3, Vec3)> {
+        match self {
+            PathState::Idle => None,
+            PathState::Recording { path, time, since_keyframe } => {
+                if *since_keyframe >= RECORD_INTERVAL {
+                    *since_keyframe = 0.0;
+                    path.keyframes.push(Keyframe {
+                        time: *time,
+                        pos: camera.pos,
+                        front: camera.front,
+                    });
+                }
+
+                *time += dt;
+                *since_keyframe += dt;
+                None
+            }
+            PathState::Playing { path, time } => {
+                *time += dt;
+                if *time > path.duration() {
+                    *self = PathState::Idle;
+                    return None;
+                }
+
+                path.sample(*time)
+            }
+        }
+    }
+}
+
+/// One step of a scripted tour, see `CameraMoves`
+#[derive(Clone, Copy, Debug)]
+pub struct CameraMove {
+    /// `None` stays where the last move ended, for pauses
+    pub to: Option<Vec3>,
+    pub seconds: f32,
+    /// keeps the camera pointed at this while moving
+    pub look_at: Option<Vec3>,
+}
+
+/*
+Moves queued by scripts with `camera.move_to`, run one after the other. Each
+one slides the goal of the fly camera from where the last one ended to its
+target, easing in and out, and the camera follows the goal through its usual
+`SecondOrderDynamics`. Input is ignored until the queue is empty.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct CameraMoves {
+    queue: VecDeque<CameraMove>,
+    /// where the current move started
+    from: Vec3,
+    /// into the current move
+    time: f32,
+}
+
+impl CameraMoves {
+    /// `from` is where the camera is, used if nothing is queued yet
+    pub fn push(&mut self, from: Vec3, step: CameraMove) {
+        if self.queue.is_empty() {
+            self.from = from;
+            self.time = 0.0;
+        }
+
+        self.queue.push_back(step);
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    pub fn is_moving(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Where the goal of the camera is now and what it should look at, or
+    /// `None` once everything queued is done
+    pub fn advance(&mut self, dt: f32) -> Option<(Vec3, Option<Vec3>)> {
+        if self.queue.is_empty() {
+            return None;
+        }
+        self.time += dt;
+
+        /* finished moves hand what is left of `dt` to the next one */
+        while let Some(step) = self.queue.front() {
+            if self.time < step.seconds {
+                break;
+            }
+
+            self.time -= step.seconds;
+            self.from = step.to.unwrap_or(self.from);
+            self.queue.pop_front();
+        }
+
+        let Some(step) = self.queue.front() else {
+            /* the last frame lands exactly on the target */
+            return Some((self.from, None));
+        };
+
+        let t = (self.time / step.seconds).clamp(0.0, 1.0);
+        let eased = t * t * (3.0 - 2.0 * t);
+
+        Some((self.from.lerp(step.to.unwrap_or(self.from), eased), step.look_at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec3, Vec3};
+
+    use super::{CameraMove, CameraMoves, CameraPath, Keyframe};
+
+    fn path() -> CameraPath {
+        let keyframe = |time, x| Keyframe { time, pos: vec3(x, 0.0, 0.0), front: Vec3::Z };
+        CameraPath { keyframes: vec![keyframe(0.0, 0.0), keyframe(1.0, 4.0), keyframe(2.5, 6.0)] }
+    }
+
+    #[test]
+    fn sample_stays_on_the_keyframes_at_the_ends() {
+        let path = path();
+
+        assert_eq!(CameraPath::default().sample(0.0), None);
+        assert_eq!(path.sample(-1.0), Some((Vec3::ZERO, Vec3::Z)));
+        assert_eq!(path.sample(0.0), Some((Vec3::ZERO, Vec3::Z)));
+        assert_eq!(path.sample(2.5), Some((vec3(6.0, 0.0, 0.0), Vec3::Z)));
+        assert_eq!(path.sample(10.0), Some((vec3(6.0, 0.0, 0.0), Vec3::Z)));
+
+        let (pos, _) = path.sample(1.0).unwrap();
+        assert!(pos.distance(vec3(4.0, 0.0, 0.0)) < 1e-5);
+        let (pos, _) = path.sample(0.5).unwrap();
+        assert!(pos.x > 0.0 && pos.x < 4.0);
+    }
+
+    #[test]
+    fn saved_paths_load_the_same() {
+        let file = std::env::temp_dir().join(format!("camera_path_test_{}.txt", std::process::id()));
+        let path = path();
+
+        path.save(&file).unwrap();
+        let loaded = CameraPath::load(&file);
+        std::fs::remove_file(&file).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.keyframes.len(), path.keyframes.len());
+        for (a, b) in loaded.keyframes.iter().zip(&path.keyframes) {
+            assert_eq!((a.time, a.pos, a.front), (b.time, b.pos, b.front));
+        }
+    }
+
+    #[test]
+    fn moves_end_on_their_target() {
+        let mut moves = CameraMoves::default();
+        let to = vec3(10.0, 0.0, 0.0);
+        moves.push(Vec3::ZERO, CameraMove { to: Some(to), seconds: 1.0, look_at: None });
+
+        let (goal, _) = moves.advance(0.5).unwrap();
+        assert!(goal.distance(vec3(5.0, 0.0, 0.0)) < 1e-5);
+        assert_eq!(moves.advance(0.5), Some((to, None)));
+        assert_eq!(moves.advance(0.5), None);
+        assert!(!moves.is_moving());
+    }
+
+    #[test]
+    fn zero_second_moves_jump_to_their_target() {
+        let mut moves = CameraMoves::default();
+        let to = vec3(0.0, 3.0, 0.0);
+        moves.push(Vec3::ZERO, CameraMove { to: Some(to), seconds: 0.0, look_at: None });
+        moves.push(Vec3::ZERO, CameraMove { to: None, seconds: 1.0, look_at: Some(Vec3::ZERO) });
+
+        /* even without time passing */
+        assert_eq!(moves.advance(0.0), Some((to, Some(Vec3::ZERO))));
+        assert_eq!(moves.advance(0.5), Some((to, Some(Vec3::ZERO))));
+        assert_eq!(moves.advance(0.5), Some((to, None)));
+        assert_eq!(moves.advance(0.0), None);
+    }
+}
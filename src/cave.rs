@@ -0,0 +1,146 @@
+use std::collections::{HashSet, VecDeque};
+
+use glam::IVec3;
+
+use crate::player::UP;
+
+/*
+Questions about the shape of the caves, asked by scripts. Everything works on
+`solid`, which is `None` for voxels that aren't loaded: searches treat those
+as walls, so they never wander off into parts of the world that don't exist.
+*/
+
+const NEIGHBOURS: [IVec3; 6] = [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z];
+
+/// The air voxel closest to `start` (by steps through any voxel), at most
+/// `max_distance` steps away
+pub fn nearest_air(solid: &impl Fn(IVec3) -> Option<bool>, start: IVec3, max_distance: i32) -> Option<IVec3> {
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([(start, 0)]);
+
+    while let Some((pos, distance)) = queue.pop_front() {
+        match solid(pos) {
+            Some(false) => return Some(pos),
+            None => continue,
+            Some(true) => (),
+        }
+        if distance == max_distance {
+            continue;
+        }
+
+        for ofs in NEIGHBOURS {
+            if seen.insert(pos + ofs) {
+                queue.push_back((pos + ofs, distance + 1));
+            }
+        }
+    }
+
+    None
+}
+
+/// How many air voxels are connected to `start`, counting up to `limit`.
+/// Zero if `start` isn't air
+pub fn chamber_size(solid: &impl Fn(IVec3) -> Option<bool>, start: IVec3, limit: usize) -> usize {
+    if solid(start) != Some(false) {
+        return 0;
+    }
+
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(pos) = queue.pop_front() {
+        if seen.len() >= limit {
+            break;
+        }
+
+        for ofs in NEIGHBOURS {
+            let next = pos + ofs;
+            if solid(next) == Some(false) && seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    seen.len().min(limit)
+}
+
+/// The first solid voxel under `start`, at most `max_distance` down
+pub fn ground_below(solid: &impl Fn(IVec3) -> Option<bool>, start: IVec3, max_distance: i32) -> Option<IVec3> {
+    let down = -UP.as_ivec3();
+
+    (0..=max_distance)
+        .map(|i| start + down * i)
+        .take_while(|pos| solid(*pos).is_some())
+        .find(|pos| solid(*pos) == Some(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{ivec3, IVec3};
+
+    use super::{chamber_size, ground_below, nearest_air};
+
+    /// Rock everywhere but `air`, and nothing loaded on the plane x = `unloaded_x`
+    fn world(air: Vec<IVec3>, unloaded_x: i32) -> impl Fn(IVec3) -> Option<bool> {
+        move |pos| (pos.x != unloaded_x).then(|| !air.contains(&pos))
+    }
+
+    #[test]
+    fn nearest_air_stops_at_max_distance() {
+        let solid = world(vec![ivec3(0, 3, 0), ivec3(0, -4, 0)], i32::MAX);
+
+        assert_eq!(nearest_air(&solid, ivec3(0, 3, 0), 8), Some(ivec3(0, 3, 0)));
+        assert_eq!(nearest_air(&solid, IVec3::ZERO, 8), Some(ivec3(0, 3, 0)));
+        assert_eq!(nearest_air(&solid, IVec3::ZERO, 2), None);
+    }
+
+    #[test]
+    fn nearest_air_does_not_search_through_unloaded_voxels() {
+        let solid = world(vec![ivec3(2, 0, 0)], 1);
+
+        assert_eq!(nearest_air(&solid, IVec3::ZERO, 6), None);
+        assert_eq!(nearest_air(&solid, ivec3(3, 0, 0), 6), Some(ivec3(2, 0, 0)));
+    }
+
+    #[test]
+    fn chamber_size_counts_connected_air_up_to_limit() {
+        let room = (0..27).map(|i| ivec3(i % 3, i / 3 % 3, i / 9)).collect();
+        let solid = world(room, i32::MAX);
+
+        assert_eq!(chamber_size(&solid, IVec3::ONE, 100), 27);
+        assert_eq!(chamber_size(&solid, IVec3::ONE, 10), 10);
+        assert_eq!(chamber_size(&solid, ivec3(5, 0, 0), 100), 0);
+    }
+
+    #[test]
+    fn chamber_size_ends_at_unloaded_voxels() {
+        /* a tunnel along x, cut by the unloaded plane */
+        let tunnel = (-3..6).map(|x| ivec3(x, 0, 0)).collect();
+        let solid = world(tunnel, 2);
+
+        assert_eq!(chamber_size(&solid, IVec3::ZERO, 100), 5);
+        assert_eq!(chamber_size(&solid, ivec3(2, 0, 0), 100), 0);
+    }
+
+    #[test]
+    fn ground_below_goes_down_until_max_distance() {
+        /* down is +y */
+        let shaft = (0..5).map(|y| ivec3(0, y, 0)).collect();
+        let solid = world(shaft, i32::MAX);
+
+        assert_eq!(ground_below(&solid, IVec3::ZERO, 10), Some(ivec3(0, 5, 0)));
+        assert_eq!(ground_below(&solid, IVec3::ZERO, 4), None);
+        assert_eq!(ground_below(&solid, ivec3(0, -1, 0), 10), Some(ivec3(0, -1, 0)));
+    }
+
+    #[test]
+    fn ground_below_stops_at_unloaded_voxels() {
+        let solid = move |pos: IVec3| match pos.y {
+            3 => None,
+            y => Some(y > 5),
+        };
+
+        assert_eq!(ground_below(&solid, IVec3::ZERO, 10), None);
+        assert_eq!(ground_below(&solid, ivec3(0, 4, 0), 10), Some(ivec3(0, 6, 0)));
+    }
+}
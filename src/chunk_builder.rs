@@ -1,10 +1,18 @@
-use std::{sync::{Arc, LazyLock}, time::Duration};
+use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc, LazyLock}, time::Duration};
 
 use chaos_vk::graphics::{camera::Camera, vk::MemAllocators};
 use tokio::sync::{mpsc::{channel, unbounded_channel, Receiver, Sender, UnboundedReceiver, UnboundedSender}, Mutex};
 
 use crate::{chunkmesh::ChunkMesh, world::{Chunk, ChunkKey, CHUNK_SIZE, DRAW_DISTANCE}};
 
+/// How many chunks are loaded around the camera on the x and z axes
+pub const LOAD_RADIUS: isize = 3;
+
+/// Distance from the camera to the edge of the loaded region
+pub fn streaming_radius() -> f32 {
+    (LOAD_RADIUS * CHUNK_SIZE as isize) as f32
+}
+
 #[derive(Clone)]
 pub enum ChunkBuilderCommands {
     NewChunk(ChunkKey, Vec<ChunkKey>),
@@ -23,6 +31,9 @@ pub struct  ChunkBuilder {
 
     data_sender: Sender<ChunkBuilderChannelData>,
     pub data_recv: Receiver<ChunkBuilderChannelData>,
+
+    /// commands sent and not done with yet, counted up by the sender
+    pub pending: Arc<AtomicUsize>,
 }
 
 impl ChunkBuilder {
@@ -38,36 +49,51 @@ impl ChunkBuilder {
 
             data_sender: tx2,
             data_recv: rx2,
+
+            pending: Arc::new(AtomicUsize::new(0)),
         }
     }
 
-    pub fn begin_loop(&mut self, allocators: Arc<chaos_vk::graphics::vk::MemAllocators>) {
-        (-3..3).for_each(|id| {
-            let rx = self.command_recv.clone();
-            let tx = self.data_sender.clone();
-            let allocators = allocators.clone();
-
-            tokio::task::spawn(async move {
-                dbg!(id);
-                loop {
-                    let mut recv = rx.lock().await;
-                    if let Some(command) = recv.recv().await {
-                        match command {
-                            ChunkBuilderCommands::NewChunk(_, vec) => todo!(),
-                            ChunkBuilderCommands::Info(camera, existing_chunks) => {
-                                on_info(camera, existing_chunks, allocators.clone(), tx.clone(), id)
+    /*
+    One task per column of the loaded region, whichever is free takes the next
+    command and builds its own column. With `in_order` a single task builds
+    every column of each command in the same order instead: slower, but what
+    comes back only depends on the camera, for headless runs
+    */
+    pub fn begin_loop(&mut self, allocators: Arc<chaos_vk::graphics::vk::MemAllocators>, in_order: bool) {
+        if in_order {
+            self.spawn_task(allocators, (-LOAD_RADIUS..LOAD_RADIUS).collect());
+        } else {
+            (-LOAD_RADIUS..LOAD_RADIUS).for_each(|id| self.spawn_task(allocators.clone(), vec![id]));
+        }
+    }
+
+    fn spawn_task(&self, allocators: Arc<MemAllocators>, columns: Vec<isize>) {
+        let rx = self.command_recv.clone();
+        let tx = self.data_sender.clone();
+        let pending = self.pending.clone();
+
+        tokio::task::spawn(async move {
+            dbg!(&columns);
+            loop {
+                let mut recv = rx.lock().await;
+                if let Some(command) = recv.recv().await {
+                    match command {
+                        ChunkBuilderCommands::NewChunk(_, vec) => todo!(),
+                        ChunkBuilderCommands::Info(camera, existing_chunks) => {
+                            for &id in &columns {
+                                on_info(camera, existing_chunks.clone(), allocators.clone(), tx.clone(), id)
                                     .await;
-                            },
-                        }
+                            }
+                        },
                     }
-
-                    // tokio::time::sleep(Duration::from_millis(16)).await;
+                    pending.fetch_sub(1, Ordering::SeqCst);
                 }
-            });
+
+                // tokio::time::sleep(Duration::from_millis(16)).await;
+            }
         });
     }
-    
-
 }
 
 pub async fn on_info(
@@ -81,7 +107,7 @@ pub async fn on_info(
     let (i,j,k) = Chunk::get_ijk_chunkspace(camera.pos);
 
     let dx = id;
-    for dz in -3..3 {
+    for dz in -LOAD_RADIUS..LOAD_RADIUS {
         'y: for dy in -2..2 {
             let k = (i + dx, j + dy, k + dz);
             
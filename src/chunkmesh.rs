@@ -1,8 +1,10 @@
 use std::{collections::HashMap, sync::Arc};
 
-use chaos_vk::graphics::{buffer::{VkBuffer, VkIterBuffer}, command::{BuilderType, SecBuilderType, SecondaryCmdBufType, VkBuilder}, utils::descriptor_set, vertex::{InstanceData, PosVertex}, vk::{MemAllocators, Vk}};
+use chaos_vk::graphics::{buffer::{VkBuffer, VkIterBuffer}, command::{BuilderType, SecBuilderType, SecondaryCmdBufType, VkBuilder}, vertex::InstanceData, vk::MemAllocators};
 use glam::{Mat4, Quat, Vec3};
-use vulkano::{buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer}, command_buffer::{CommandBufferInheritanceInfo, CommandBufferInheritanceRenderPassInfo, DispatchIndirectCommand, DrawIndexedIndirectCommand, DrawIndirectCommand, SecondaryAutoCommandBuffer, SecondaryCommandBufferAbstract}, descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}, pipeline::{GraphicsPipeline, Pipeline}, render_pass::{Framebuffer, Subpass}};
+use vulkano::{buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer}, command_buffer::{allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferInheritanceInfo, CommandBufferInheritanceRenderPassInfo, DispatchIndirectCommand, DrawIndexedIndirectCommand, DrawIndirectCommand, SecondaryAutoCommandBuffer, SecondaryCommandBufferAbstract}, descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}, pipeline::{GraphicsPipeline, Pipeline}, render_pass::{Framebuffer, Subpass}};
+
+use crate::{vertex::ChunkVertex, vk_mod::Gpu};
 
 #[derive(BufferContents, Clone, Copy)]
 #[repr(C)]
@@ -12,26 +14,30 @@ pub struct Model {
 
 #[derive(Clone)]
 pub struct ChunkMesh {
-    pub vertices: Vec<PosVertex>,
-    pub indices: Vec<u32>,
+    pub vertices: Vec<ChunkVertex>,
     pub instances: Vec<InstanceData>,
 
-    pub vbo: Subbuffer<[PosVertex]>,
+    pub vbo: Subbuffer<[ChunkVertex]>,
     pub ibo: Subbuffer<[InstanceData]>,
-    pub ebo: Subbuffer<[u32]>,
     pub ubo: Option<VkBuffer<Model>>,
     pub dc: Option<Arc<PersistentDescriptorSet>>,
 
+    /// recorded draw of this chunk, one per swapchain image (see `renderer::chunk_secondaries`),
+    /// empty until it is first drawn
     pub cbo: Vec<Option<SecondaryCmdBufType>>,
+    /// hash of what `cbo` was recorded with
+    pub cbo_key: u64,
 
     pub indb: Option<Subbuffer<[DrawIndexedIndirectCommand]>>,
     
     pub visible: bool,
+    /// lod of the chunk when it was meshed
+    pub lod: usize,
 }
 
 /* TODO: on CHAOS_VK add static index and vertex buffers */
 impl ChunkMesh {
-    pub fn new(allocators: Arc<MemAllocators>, vertices: &Vec<PosVertex>, indices: &Vec<u32>) -> Self {
+    pub fn new(allocators: Arc<MemAllocators>, vertices: &Vec<ChunkVertex>) -> Self {
         let instances = vec![InstanceData {ofs: [0.0, 0.0, 0.0]}];
 
         let vertex_buf = Buffer::from_iter(
@@ -48,20 +54,6 @@ impl ChunkMesh {
         )
         .expect("failed to create buffer");
 
-        let index_buf = Buffer::from_iter(
-            allocators.memory.clone(),
-            BufferCreateInfo {
-                usage: BufferUsage::INDEX_BUFFER,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                ..Default::default()
-            },
-            indices.to_vec(),
-        )
-        .expect("failed to create buffer");
-
         let instance_buf = Buffer::from_iter(
             allocators.memory.clone(),
             BufferCreateInfo {
@@ -78,28 +70,28 @@ impl ChunkMesh {
 
         Self {
             vertices: vertices.to_vec(),
-            indices: indices.to_vec(),
             instances: instances.to_vec(),
 
             vbo: vertex_buf,
-            ebo: index_buf,
             ibo: instance_buf,
             ubo: None,
             dc: None,
 
-            cbo: vec![None; 3],
+            cbo: vec![],
+            cbo_key: 0,
 
             indb: None,
             visible: true,
+            lod: 0,
         }
     }
 
-    pub fn get_indb(&mut self, vk: &Arc<Vk>) -> Subbuffer<[DrawIndexedIndirectCommand]> {
+    pub fn get_indb(&mut self, gpu: &Arc<Gpu>) -> Subbuffer<[DrawIndexedIndirectCommand]> {
         if self.indb.is_some() {
             self.indb.clone().unwrap()
         } else {
             let buffer  = Buffer::from_iter(
-                vk.allocators.memory.clone(), 
+                gpu.allocators.memory.clone(), 
                 BufferCreateInfo {
                     usage: BufferUsage::INDIRECT_BUFFER,
                     ..Default::default()
@@ -118,14 +110,13 @@ impl ChunkMesh {
         }
     }
 
-    pub fn get_dc(&mut self, vk: Arc<Vk>, pipeline: Arc<GraphicsPipeline>, ubo: VkBuffer<Model>) -> Arc<PersistentDescriptorSet> {
+    pub fn get_dc(&mut self, gpu: Arc<Gpu>, pipeline: Arc<GraphicsPipeline>, ubo: VkBuffer<Model>) -> Arc<PersistentDescriptorSet> {
         if self.dc.is_none() {
-            self.dc = Some(descriptor_set(
-                vk.clone(), 
+            self.dc = Some(gpu.descriptor_set(
                 1,
                 pipeline.clone(), 
                 [WriteDescriptorSet::buffer(0, ubo.content.clone())]
-            ).0);
+            ));
 
             return self.dc.clone().unwrap();
         } else {
@@ -142,9 +133,13 @@ impl ChunkMesh {
     /// };
     /// ```
 
+    pub fn quads(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
     pub fn get_indirect_command(&self) -> DrawIndexedIndirectCommand {
         DrawIndexedIndirectCommand {
-            index_count: self.indices.len() as u32,
+            index_count: (self.quads() * 6) as u32,
             instance_count: 1,
             first_index: 0,
             vertex_offset: 0,
@@ -152,13 +147,84 @@ impl ChunkMesh {
         }
     }
 
-    pub fn bind_buffers(&self, builder: &mut BuilderType) {
+    /// `quad_ebo` must hold at least `self.quads()` quads
+    pub fn bind_buffers<L>(&self, builder: &mut AutoCommandBufferBuilder<L, Arc<StandardCommandBufferAllocator>>, quad_ebo: &QuadIndexBuffer) {
         builder
             .bind_vertex_buffers(0, 
                 (self.vbo.clone(), self.ibo.clone())
             )
             .unwrap()
-            .bind_index_buffer(self.ebo.clone())
+            .bind_index_buffer(quad_ebo.ebo.clone().expect("quad index buffer reserved for the mesh"))
             .unwrap();
     }
 }
+
+/// Index buffer shared by every chunk mesh. Since every face is a quad 
+/// laid out the same way, the indices only depend on how many quads are drawn.
+pub struct QuadIndexBuffer {
+    /// `None` until the first `reserve` of an `empty` buffer
+    pub ebo: Option<Subbuffer<[u32]>>,
+    pub quads: usize,
+}
+
+impl QuadIndexBuffer {
+    pub fn new(allocators: Arc<MemAllocators>, quads: usize) -> Self {
+        let ebo = Buffer::from_iter(
+            allocators.memory.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::INDEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            quad_indices(quads),
+        )
+        .expect("failed to create buffer");
+
+        Self {
+            ebo: Some(ebo),
+            quads,
+        }
+    }
+
+    /// Indexes nothing and allocates nothing, for worlds that never draw
+    pub fn empty() -> Self {
+        Self {
+            ebo: None,
+            quads: 0,
+        }
+    }
+
+    /// Grows (by doubling) the buffer until it can index `quads` quads
+    pub fn reserve(&mut self, allocators: Arc<MemAllocators>, quads: usize) {
+        if quads <= self.quads {
+            return;
+        }
+
+        let mut new_quads = self.quads.max(1);
+        while new_quads < quads {
+            new_quads *= 2;
+        }
+
+        *self = Self::new(allocators, new_quads);
+    }
+}
+
+pub fn quad_indices(quads: usize) -> Vec<u32> {
+    let mut indices = Vec::with_capacity(quads * 6);
+
+    for quad in 0..quads as u32 {
+        let start_vertex_idx = quad * 4;
+
+        indices.push(start_vertex_idx);
+        indices.push(start_vertex_idx + 1);
+        indices.push(start_vertex_idx + 2);
+        indices.push(start_vertex_idx + 2);
+        indices.push(start_vertex_idx + 3);
+        indices.push(start_vertex_idx);
+    }
+
+    indices
+}
@@ -1,7 +1,7 @@
 use chaos_vk::graphics::camera::Camera;
 use glam::{vec3, vec4, Mat4, Vec3, Vec3A, Vec4};
 
-use crate::{chunkmesh::ChunkMesh, world::{Chunk, ChunkKey, CHUNK_SIZE}};
+use crate::{chunkmesh::ChunkMesh, window::{WindowState, Z_FAR, Z_NEAR}, world::{Chunk, ChunkKey, CHUNK_SIZE}};
 
 
 /*
@@ -11,13 +11,14 @@ A set of utilities to help with culling meshes that are not in the view frustrum
 pub struct ChunkCuller {}
 
 impl ChunkCuller {
-    pub fn is_visible(k: ChunkKey, camera: &Camera) -> bool {
+    /// `window` has to be the one the camera projection came from
+    pub fn is_visible(k: ChunkKey, camera: &Camera, window: &WindowState) -> bool {
         let frustum = Frustum::sample_from_camera(
             camera, 
-            1200.0 / 900.0, 
-            80.0_f32.to_radians(), 
-            0.1, 
-            1000.0
+            window.aspect(), 
+            window.fov_y.to_radians(), 
+            Z_NEAR, 
+            Z_FAR
         );
 
         let pos = Chunk::get_worldpos(&k);
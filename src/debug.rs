@@ -0,0 +1,143 @@
+use bevy_ecs::system::{Commands, Resource};
+use glam::{vec3, Vec3};
+
+use crate::{shaders::fs, vertex::LineVertex, world::{Chunk, ChunkKey, CHUNK_SIZE}};
+
+/// One per lod `get_lod_by_distance` hands out, indexed by `lod_rank`.
+/// Has to match `LOD_COLORS` in `shaders::fs`
+pub const LOD_COLORS: [Vec3; 4] = [
+    vec3(0.2, 0.9, 0.2),
+    vec3(0.9, 0.9, 0.2),
+    vec3(0.9, 0.5, 0.1),
+    vec3(0.9, 0.2, 0.2),
+];
+
+const CULLED_COLOR: Vec3 = vec3(1.0, 0.0, 0.0);
+
+/// How the chunks are colored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DebugMode {
+    Shaded,
+    /// each chunk colored by its `lod`
+    Lod,
+    Normals,
+}
+
+/// Render debug views, toggled from imgui
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct DebugView {
+    pub mode: DebugMode,
+    pub wireframe: bool,
+    /// outlines every loaded chunk, colored by lod (red if culled)
+    pub chunk_bounds: bool,
+    /// draws the chunks `ChunkCuller` threw away, tinted red
+    pub show_culled: bool,
+}
+
+impl DebugView {
+    pub fn new() -> Self {
+        Self {
+            mode: DebugMode::Shaded,
+            wireframe: false,
+            chunk_bounds: false,
+            show_culled: false,
+        }
+    }
+
+    pub fn push_constants(&self, lod: usize, culled: bool) -> fs::DebugParams {
+        let debug_mode = match self.mode {
+            DebugMode::Shaded => 0,
+            DebugMode::Lod => 1,
+            DebugMode::Normals => 2,
+        };
+
+        fs::DebugParams {
+            debug_mode,
+            lod: lod as u32,
+            culled: culled as u32,
+        }
+    }
+}
+
+/// Lods go 0, 2, 4, 8, this turns them into 0, 1, 2, 3. Same as `lod_rank` in `shaders::fs`
+pub fn lod_rank(lod: usize) -> usize {
+    if lod == 0 {
+        0
+    } else {
+        (lod.trailing_zeros() as usize).min(LOD_COLORS.len() - 1)
+    }
+}
+
+pub fn lod_color(lod: usize) -> Vec3 {
+    LOD_COLORS[lod_rank(lod)]
+}
+
+/// Outline of the voxel the crosshair is on
+pub const TARGET_COLOR: Vec3 = vec3(1.0, 1.0, 1.0);
+pub const SELECTION_COLOR: Vec3 = vec3(0.2, 0.6, 1.0);
+/// where the clipboard would be pasted
+pub const PASTE_COLOR: Vec3 = vec3(0.3, 1.0, 0.4);
+
+/// The 12 edges of a chunk as a line list, in world space
+pub fn chunk_bounds(key: ChunkKey, lod: usize, culled: bool) -> [LineVertex; 24] {
+    let color = if culled { CULLED_COLOR } else { lod_color(lod) };
+    box_lines(Chunk::get_worldpos(&key), Vec3::splat(CHUNK_SIZE as f32), color)
+}
+
+/// The 12 edges of a cube as a line list
+pub fn box_lines(min: Vec3, size: Vec3, color: Vec3) -> [LineVertex; 24] {
+    let color = color.to_array();
+
+    let corner = |i: usize| {
+        (min + vec3((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32) * size).to_array()
+    };
+
+    /* corners are indexed by their xyz bits, each edge flips a single one */
+    const EDGES: [(usize, usize); 12] = [
+        (0, 1), (2, 3), (4, 5), (6, 7),
+        (0, 2), (1, 3), (4, 6), (5, 7),
+        (0, 4), (1, 5), (2, 6), (3, 7),
+    ];
+
+    let mut lines = [LineVertex { pos: [0.0; 3], color }; 24];
+    for (i, (a, b)) in EDGES.iter().enumerate() {
+        lines[i * 2].pos = corner(*a);
+        lines[i * 2 + 1].pos = corner(*b);
+    }
+
+    lines
+}
+
+pub fn startup(mut commands: Commands) {
+    commands.insert_resource(DebugView::new());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use chaos_vk::graphics::camera::Camera;
+    use glam::Vec3;
+
+    use super::lod_color;
+    use crate::{chunk_builder::get_lod_by_distance, world::{CHUNK_SIZE, DRAW_DISTANCE}};
+
+    #[test]
+    fn every_lod_has_its_own_color() {
+        let mut camera = Camera::new();
+        camera.pos = Vec3::ZERO;
+
+        /* chunks going away from the camera until past the draw distance */
+        let lods: BTreeSet<usize> = (0..=(DRAW_DISTANCE as isize / CHUNK_SIZE as isize) + 1)
+            .map(|i| get_lod_by_distance(&camera, (i, 0, 0)))
+            .collect();
+        assert_eq!(lods.iter().copied().collect::<Vec<_>>(), [0, 2, 4, 8]);
+
+        let colors: Vec<Vec3> = lods.iter().map(|lod| lod_color(*lod)).collect();
+        for (i, a) in colors.iter().enumerate() {
+            for b in &colors[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::path::Path;
+
+use bevy_ecs::world::{Mut, World};
+use glam::IVec3;
+use imgui::Ui;
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::{brush::Brushes, history::WORLD_FILE, input::{Binding, Bindings}, player::MoveMode, raycast::RayHit, renderer::Renderer, schematic::Clipboard, world::{ChunkWorld, Voxel}};
+
+/// What gets placed with the right mouse button
+pub const PLACED_VOXEL: Voxel = Voxel { id: 1 };
+
+pub fn register_actions(bindings: &mut Bindings) {
+    bindings.register("dig", &[MouseButton::Left]);
+    bindings.register("place", &[MouseButton::Right]);
+    bindings.register("brush", &[MouseButton::Middle]);
+    bindings.register("undo", &[Binding::with(VirtualKeyCode::LControl, VirtualKeyCode::Z)]);
+    bindings.register("redo", &[Binding::with(VirtualKeyCode::LControl, VirtualKeyCode::Y)]);
+    bindings.register("select_corner", &[VirtualKeyCode::G]);
+    bindings.register("copy", &[Binding::with(VirtualKeyCode::LControl, VirtualKeyCode::C)]);
+    bindings.register("paste", &[Binding::with(VirtualKeyCode::LControl, VirtualKeyCode::V)]);
+    bindings.register("rotate_clipboard", &[VirtualKeyCode::R]);
+    bindings.register("mirror_clipboard", &[VirtualKeyCode::M]);
+}
+
+/*
+Digging removes the voxel under the crosshair, placing fills the one in front
+of the face that is looked at, and the brush button applies the current brush
+where the crosshair hits. `ChunkWorld::set_voxel` keeps the edits and
+re-meshes the chunks it touched at the next update. Each of them, like a
+paste, is one step of the undo history.
+*/
+pub fn handle_action(action: &str, renderer: &Renderer, world: &mut World) {
+    let brush = world.resource::<Brushes>().current;
+    world.resource_scope(|world, mut clipboard: Mut<Clipboard>| {
+        let mut chunkworld = world.resource_mut::<ChunkWorld>();
+
+        match action {
+            "undo" => {
+                chunkworld.undo();
+                return;
+            }
+            "redo" => {
+                chunkworld.redo();
+                return;
+            }
+            "copy" => {
+                clipboard.copy(&chunkworld);
+                return;
+            }
+            "rotate_clipboard" => {
+                clipboard.rotate();
+                return;
+            }
+            "mirror_clipboard" => {
+                clipboard.mirror();
+                return;
+            }
+            _ => (),
+        }
+
+        let Some(hit) = renderer.target else {
+            return;
+        };
+
+        match action {
+            "dig" => {
+                chunkworld.set_voxel(hit.voxel, Voxel { id: 0 });
+            }
+            "place" => {
+                /* the camera is inside of the voxel */
+                if hit.normal == IVec3::ZERO {
+                    return;
+                }
+
+                let pos = hit.voxel + hit.normal;
+                if blocks_player(renderer, pos) {
+                    return;
+                }
+
+                chunkworld.set_voxel(pos, PLACED_VOXEL);
+            }
+            "brush" => {
+                brush.apply(&mut chunkworld, hit.point);
+            }
+            "select_corner" => {
+                clipboard.select_corner(hit.voxel);
+                return;
+            }
+            "paste" => {
+                clipboard.paste(&mut chunkworld, paste_origin(&hit));
+                return;
+            }
+            _ => return,
+        }
+
+        chunkworld.history.commit(action);
+    });
+}
+
+/// Pastes go against the face that is looked at
+pub fn paste_origin(hit: &RayHit) -> IVec3 {
+    hit.voxel + hit.normal
+}
+
+/// Undo/redo buttons, the last edits, and saving them all to `WORLD_FILE`
+pub fn history_ui(ui: &Ui, chunkworld: &mut ChunkWorld) {
+    ui.window("edit history").build(|| {
+        if ui.button("undo") {
+            chunkworld.undo();
+        }
+        ui.same_line();
+        if ui.button("redo") {
+            chunkworld.redo();
+        }
+        ui.same_line();
+        ui.text(format!("({} to redo)", chunkworld.history.redo_len()));
+
+        let mut depth = chunkworld.history.depth as i32;
+        if ui.slider("depth", 1, 1000, &mut depth) {
+            chunkworld.history.set_depth(depth as usize);
+        }
+
+        for (i, label) in chunkworld.history.undo_labels().take(10).enumerate() {
+            ui.text(format!("{i}: {label}"));
+        }
+
+        ui.separator();
+        if ui.button("save edits") {
+            match chunkworld.save_edits(Path::new(WORLD_FILE)) {
+                Ok(()) => println!("saved edits to {WORLD_FILE}"),
+                Err(e) => println!("failed to save edits: {e}"),
+            }
+        }
+        ui.same_line();
+        if ui.button("load edits") {
+            if let Err(e) = chunkworld.load_edits(Path::new(WORLD_FILE)) {
+                println!("failed to load edits: {e}");
+            }
+        }
+    });
+}
+
+/// Whether a voxel at `pos` would end up inside of the walking player
+fn blocks_player(renderer: &Renderer, pos: IVec3) -> bool {
+    if renderer.move_mode != MoveMode::Walk {
+        return false;
+    }
+
+    let player = &renderer.player;
+    player.capsule().overlaps(player.feet, &|voxel| voxel == pos)
+}
@@ -0,0 +1,80 @@
+use bevy_ecs::system::{Commands, Resource};
+use glam::{vec3, Vec3};
+
+use crate::{chunk_builder::streaming_radius, shaders::fs, world::DRAW_DISTANCE};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FogMode {
+    Linear,
+    Exponential,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum FogCommand {
+    Color(Vec3),
+    Range(f32, f32),
+    Density(f32),
+    Mode(FogMode),
+}
+
+/// Distance fog for the chunk shaders. The color is also used to clear the screen
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: Vec3,
+    pub start: f32,
+    pub end: f32,
+    /// only used by `FogMode::Exponential`
+    pub density: f32,
+}
+
+impl Fog {
+    pub fn new() -> Self {
+        let end = Self::max_distance();
+
+        Self {
+            mode: FogMode::Linear,
+            color: vec3(0.1, 0.2, 0.3),
+            start: end * 0.5,
+            end,
+            density: 0.01,
+        }
+    }
+
+    /// Past this, chunks are either not loaded yet or about to be unloaded
+    pub fn max_distance() -> f32 {
+        streaming_radius().min(DRAW_DISTANCE)
+    }
+
+    pub fn apply(&mut self, command: FogCommand) {
+        match command {
+            FogCommand::Color(color) => self.color = color,
+            FogCommand::Range(start, end) => {
+                self.end = end.clamp(0.0, Self::max_distance());
+                self.start = start.clamp(0.0, self.end);
+            }
+            FogCommand::Density(density) => self.density = density.max(0.0),
+            FogCommand::Mode(mode) => self.mode = mode,
+        }
+    }
+
+    pub fn clear_color(&self) -> [f32; 4] {
+        [self.color.x, self.color.y, self.color.z, 1.0]
+    }
+
+    pub fn get_ubo_data(&self) -> fs::Fog {
+        let mode = match self.mode {
+            FogMode::Linear => 0.0,
+            FogMode::Exponential => 1.0,
+        };
+
+        fs::Fog {
+            fog_color: [self.color.x, self.color.y, self.color.z, mode],
+            fog_params: [self.start, self.end, self.density, 0.0],
+        }
+    }
+}
+
+pub fn startup(mut commands: Commands) {
+    commands.insert_resource(Fog::new());
+}
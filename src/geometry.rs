@@ -66,14 +66,47 @@ pub fn sphere(iterations: usize, radius: f32, pos: Vec3) -> GeometryData {
     }
 }
 
+/// Axis aligned cube centered on `pos`
+pub fn cube(size: f32, pos: Vec3) -> GeometryData {
+    let h = size * 0.5;
+
+    /* corners indexed by their xyz bits */
+    let vertices = (0..8)
+        .map(|i| PosVertex {
+            pos: [
+                pos.x + if i & 1 == 0 { -h } else { h },
+                pos.y + if i & 2 == 0 { -h } else { h },
+                pos.z + if i & 4 == 0 { -h } else { h },
+            ],
+        })
+        .collect();
+
+    let indices = vec![
+        0, 4, 6, 6, 2, 0, // -x
+        1, 3, 7, 7, 5, 1, // +x
+        0, 1, 5, 5, 4, 0, // -y
+        2, 6, 7, 7, 3, 2, // +y
+        0, 2, 3, 3, 1, 0, // -z
+        4, 5, 7, 7, 6, 4, // +z
+    ];
+
+    GeometryData {
+        vertices,
+        indices,
+    }
+}
+
 pub mod voxel_gen {
-    use chaos_vk::graphics::vertex::PosVertex;
-    use glam::Vec3;
-    use crate::world::{Voxel, CHUNK_SIZE};
+    use glam::{ivec3, IVec3, Vec3};
+    use crate::{vertex::ChunkVertex, world::{Voxel, CHUNK_SIZE}};
 
-    pub fn gen_mesh_data_culled(voxels: &Vec<Voxel>, lod: usize) -> (Vec<PosVertex>, Vec<u32>) {
+    /// Every face is emitted as 4 vertices, to be drawn with the shared
+    /// `0, 1, 2, 2, 3, 0` quad indices (see `chunkmesh::QuadIndexBuffer`).
+    /// 
+    /// `light` gives the light level at a position relative to the chunk, 
+    /// which can be outside of it
+    pub fn gen_mesh_data_culled(voxels: &Vec<Voxel>, lod: usize, light: &impl Fn(isize, isize, isize) -> u8) -> Vec<ChunkVertex> {
         let mut vertices = Vec::new();
-        let mut indices = Vec::new();
 
         for x in (0..CHUNK_SIZE).step_by(lod) {
             for y in (0..CHUNK_SIZE).step_by(lod) {
@@ -99,66 +132,168 @@ pub mod voxel_gen {
                             continue;
                         }
 
-                        let start_vertex_idx = vertices.len() as u32;
-
-                        match face_idx {
-                            0 => { // left face
-                                vertices.push(PosVertex { pos: [x as f32, y as f32, z as f32] });
-                                vertices.push(PosVertex { pos: [x as f32, y as f32, (z + lod) as f32] });
-                                vertices.push(PosVertex { pos: [x as f32, (y + lod) as f32, (z + lod) as f32] });
-                                vertices.push(PosVertex { pos: [x as f32, (y + lod) as f32, z as f32] });
-                            },
-                            1 => { // right face
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, y as f32, z as f32] });
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, (y + lod) as f32, z as f32] });
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, (y + lod) as f32, (z + lod) as f32] });
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, y as f32, (z + lod) as f32] });
-                            },
-                            2 => { // bottom face
-                                vertices.push(PosVertex { pos: [x as f32, y as f32, z as f32] });
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, y as f32, z as f32] });
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, y as f32, (z + lod) as f32] });
-                                vertices.push(PosVertex { pos: [x as f32, y as f32, (z + lod) as f32] });
-                            },
-                            3 => { // top face
-                                vertices.push(PosVertex { pos: [x as f32, (y + lod) as f32, z as f32] });
-                                vertices.push(PosVertex { pos: [x as f32, (y + lod) as f32, (z + lod) as f32] });
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, (y + lod) as f32, (z + lod) as f32] });
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, (y + lod) as f32, z as f32] });
-                            },
-                            4 => { // back face
-                                vertices.push(PosVertex { pos: [x as f32, y as f32, z as f32] });
-                                vertices.push(PosVertex { pos: [x as f32, (y + lod) as f32, z as f32] });
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, (y + lod) as f32, z as f32] });
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, y as f32, z as f32] });
-                            },
-                            5 => { // front face
-                                vertices.push(PosVertex { pos: [x as f32, y as f32, (z + lod) as f32] });
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, y as f32, (z + lod) as f32] });
-                                vertices.push(PosVertex { pos: [(x + lod) as f32, (y + lod) as f32, (z + lod) as f32] });
-                                vertices.push(PosVertex { pos: [x as f32, (y + lod) as f32, (z + lod) as f32] });
-                            },
-                            _ => {}
-                        }
+                        let corners = match face_idx {
+                            0 => [ // left face
+                                [x, y, z],
+                                [x, y, z + lod],
+                                [x, y + lod, z + lod],
+                                [x, y + lod, z],
+                            ],
+                            1 => [ // right face
+                                [x + lod, y, z],
+                                [x + lod, y + lod, z],
+                                [x + lod, y + lod, z + lod],
+                                [x + lod, y, z + lod],
+                            ],
+                            2 => [ // bottom face
+                                [x, y, z],
+                                [x + lod, y, z],
+                                [x + lod, y, z + lod],
+                                [x, y, z + lod],
+                            ],
+                            3 => [ // top face
+                                [x, y + lod, z],
+                                [x, y + lod, z + lod],
+                                [x + lod, y + lod, z + lod],
+                                [x + lod, y + lod, z],
+                            ],
+                            4 => [ // back face
+                                [x, y, z],
+                                [x, y + lod, z],
+                                [x + lod, y + lod, z],
+                                [x + lod, y, z],
+                            ],
+                            _ => [ // front face
+                                [x, y, z + lod],
+                                [x + lod, y, z + lod],
+                                [x + lod, y + lod, z + lod],
+                                [x, y + lod, z + lod],
+                            ],
+                        };
 
-                        indices.push(start_vertex_idx);
-                        indices.push(start_vertex_idx + 1);
-                        indices.push(start_vertex_idx + 2);
-                        indices.push(start_vertex_idx + 2);
-                        indices.push(start_vertex_idx + 3);
-                        indices.push(start_vertex_idx);
+                        let ao = corners.map(|corner| vertex_ao(voxels, [x, y, z], face_idx, corner, lod));
+                        let lights = corners.map(|corner| vertex_light(voxels, [x, y, z], face_idx, corner, lod, light));
+
+                        /* 
+                        the shared quad indices split the quad along the 0-2 diagonal,
+                        rotating the corners makes it split along 1-3 instead, which 
+                        keeps the AO gradient from looking anisotropic
+                        */
+                        let first = if ao[0] + ao[2] < ao[1] + ao[3] { 1 } else { 0 };
+
+                        for i in 0..4 {
+                            let c = (first + i) % 4;
+                            vertices.push(ChunkVertex::pack(corners[c], face_idx, ao[c], voxel.id, lights[c]));
+                        }
                     }
                 }
             }
         }
 
-        (vertices, indices)
+        vertices
     }
     
+    /// Outward normal of each face index used by the mesher
+    pub const FACE_NORMALS: [[isize; 3]; 6] = [
+        [-1, 0, 0],
+        [1, 0, 0],
+        [0, -1, 0],
+        [0, 1, 0],
+        [0, 0, -1],
+        [0, 0, 1],
+    ];
+
+    /// Voxels sampled for a face corner: the voxel the face is looking at, 
+    /// its two edge neighbours and the corner neighbour, all in the layer of 
+    /// voxels in front of the face
+    fn corner_samples(voxel: [usize; 3], face_idx: usize, corner: [usize; 3], lod: usize) -> [[isize; 3]; 4] {
+        let normal = FACE_NORMALS[face_idx];
+        let lod = lod as isize;
+
+        let mut layer = [0; 3];
+        let mut sides = [[0; 3]; 2];
+        let mut side_i = 0;
+
+        for axis in 0..3 {
+            layer[axis] = voxel[axis] as isize + normal[axis] * lod;
+
+            if normal[axis] == 0 {
+                sides[side_i][axis] = if corner[axis] > voxel[axis] { lod } else { -lod };
+                side_i += 1;
+            }
+        }
+
+        let at = |ofs: [isize; 3]| [layer[0] + ofs[0], layer[1] + ofs[1], layer[2] + ofs[2]];
+
+        [
+            layer,
+            at(sides[0]),
+            at(sides[1]),
+            at([
+                sides[0][0] + sides[1][0],
+                sides[0][1] + sides[1][1],
+                sides[0][2] + sides[1][2],
+            ]),
+        ]
+    }
+
+    /// Ambient occlusion of a face corner, from 0 (fully occluded) to 3
+    pub fn vertex_ao(voxels: &Vec<Voxel>, voxel: [usize; 3], face_idx: usize, corner: [usize; 3], lod: usize) -> u32 {
+        let [_, side1, side2, corner] = corner_samples(voxel, face_idx, corner, lod)
+            .map(|[x, y, z]| is_solid(voxels, x, y, z) as u32);
+
+        if side1 == 1 && side2 == 1 {
+            0
+        } else {
+            3 - (side1 + side2 + corner)
+        }
+    }
+
+    /// Smooth light level of a face corner, the average of the non solid samples
+    pub fn vertex_light(
+        voxels: &Vec<Voxel>, 
+        voxel: [usize; 3], 
+        face_idx: usize, 
+        corner: [usize; 3], 
+        lod: usize,
+        light: &impl Fn(isize, isize, isize) -> u8,
+    ) -> u32 {
+        let samples = corner_samples(voxel, face_idx, corner, lod);
+        let both_sides_solid = is_solid(voxels, samples[1][0], samples[1][1], samples[1][2])
+            && is_solid(voxels, samples[2][0], samples[2][1], samples[2][2]);
+
+        let (mut sum, mut count) = (0, 0);
+        for (i, [x, y, z]) in samples.into_iter().enumerate() {
+            /* light can't reach the corner voxel through two solid sides */
+            if (i == 3 && both_sides_solid) || (i > 0 && is_solid(voxels, x, y, z)) {
+                continue;
+            }
+
+            sum += light(x, y, z) as u32;
+            count += 1;
+        }
+
+        sum / count
+    }
+
+    /// Voxels outside of the chunk are considered empty, like in `is_visible`
+    pub fn is_solid(voxels: &Vec<Voxel>, x: isize, y: isize, z: isize) -> bool {
+        if x < 0 || x >= CHUNK_SIZE as isize
+        || y < 0 || y >= CHUNK_SIZE as isize
+        || z < 0 || z >= CHUNK_SIZE as isize {
+            return false;
+        }
+
+        voxels[x as usize * (CHUNK_SIZE * CHUNK_SIZE) + y as usize * CHUNK_SIZE + z as usize].id != 0
+    }
+
+    /// Index of the voxel containing a point in world space, inside of its chunk.
+    /// Floors first, truncating would put -0.5 in the voxel at 0
     pub fn get_voxel(pos: Vec3) -> usize {
-        let x = ((pos.x as i32 % CHUNK_SIZE as i32 + CHUNK_SIZE as i32) % CHUNK_SIZE as i32) as usize;
-        let y = ((pos.y as i32 % CHUNK_SIZE as i32 + CHUNK_SIZE as i32) % CHUNK_SIZE as i32) as usize;
-        let z = ((pos.z as i32 % CHUNK_SIZE as i32 + CHUNK_SIZE as i32) % CHUNK_SIZE as i32) as usize;
+        let pos = pos.floor().as_ivec3();
+        let x = pos.x.rem_euclid(CHUNK_SIZE as i32) as usize;
+        let y = pos.y.rem_euclid(CHUNK_SIZE as i32) as usize;
+        let z = pos.z.rem_euclid(CHUNK_SIZE as i32) as usize;
     
         x * (CHUNK_SIZE * CHUNK_SIZE) + y * CHUNK_SIZE + z
     }
@@ -171,6 +306,16 @@ pub mod voxel_gen {
         (x, y, z)
     }
 
+    /// Same layout as `get_pos`, for a box of any `size` (x, then y, then z)
+    pub fn index_in(size: IVec3, pos: IVec3) -> usize {
+        (pos.x * size.y * size.z + pos.y * size.z + pos.z) as usize
+    }
+
+    pub fn pos_in(size: IVec3, idx: usize) -> IVec3 {
+        let idx = idx as i32;
+        ivec3(idx / (size.y * size.z), (idx / size.z) % size.y, idx % size.z)
+    }
+
     pub fn get_index(x: usize, y: usize, z: usize) -> usize {
         x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE
     }
@@ -196,4 +341,28 @@ pub mod voxel_gen {
     
         voxels[neighbor_pos].id == 0
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use glam::{ivec3, vec3};
+
+    use super::voxel_gen::{get_pos, get_voxel};
+    use crate::world::{Chunk, CHUNK_SIZE};
+
+    #[test]
+    fn get_voxel_floors_below_zero() {
+        let last = CHUNK_SIZE as isize - 1;
+
+        assert_eq!(get_pos(get_voxel(vec3(-0.5, 0.5, 0.5))), (last, 0, 0));
+        assert_eq!(get_pos(get_voxel(vec3(0.5, -0.5, -64.5))), (0, last, last));
+        assert_eq!(get_pos(get_voxel(vec3(-63.5, 1.0, 2.0))), (0, 1, 2));
+    }
+
+    #[test]
+    fn get_voxel_agrees_with_locate_voxel() {
+        for pos in [vec3(-0.5, -0.5, -0.5), vec3(-64.0, 63.9, 0.0), vec3(100.2, -130.7, 7.0)] {
+            assert_eq!(get_voxel(pos), Chunk::locate_voxel(pos.floor().as_ivec3()).1);
+        }
+        assert_eq!(get_voxel(vec3(-1.0, -1.0, -1.0)), Chunk::local_index(ivec3(63, 63, 63)));
+    }
+}
@@ -0,0 +1,273 @@
+use std::{collections::{HashMap, VecDeque}, io::{self, Read, Write}};
+
+use glam::{ivec3, IVec3};
+
+/// How many edits can be undone unless changed in the UI
+pub const DEFAULT_DEPTH: usize = 100;
+
+/// Where the edits and their history are saved
+pub const WORLD_FILE: &str = "world.edits";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoxelChange {
+    pub pos: IVec3,
+    /// voxel ids
+    pub before: usize,
+    pub after: usize,
+}
+
+/// Everything one operation (a click, a brush stroke, a script, a paste) changed
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub label: String,
+    pub changes: Vec<VoxelChange>,
+}
+
+/*
+Undo and redo for the voxels of `ChunkWorld`. Changes are recorded as they
+happen and grouped into an `Edit` by `commit`, with only the first `before`
+and the last `after` of each voxel kept, so a smoothing brush going over the
+same voxels does not grow the history.
+
+`ChunkWorld` does the actual undoing, the history only keeps track of what
+to put back.
+*/
+pub struct EditHistory {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    /// changes not committed to an edit yet
+    pending: Vec<VoxelChange>,
+    /// edits kept for undoing, older ones are forgotten
+    pub depth: usize,
+}
+
+impl EditHistory {
+    pub fn new() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+            pending: vec![],
+            depth: DEFAULT_DEPTH,
+        }
+    }
+
+    /// A new change makes whatever was undone impossible to redo
+    pub fn record(&mut self, pos: IVec3, before: usize, after: usize) {
+        self.pending.push(VoxelChange { pos, before, after });
+        self.redo.clear();
+    }
+
+    /// Groups the changes recorded since the last commit into one edit
+    pub fn commit(&mut self, label: &str) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let mut changes: Vec<VoxelChange> = vec![];
+        let mut seen: HashMap<IVec3, usize> = HashMap::new();
+        for change in self.pending.drain(..) {
+            match seen.get(&change.pos) {
+                Some(i) => changes[*i].after = change.after,
+                None => {
+                    seen.insert(change.pos, changes.len());
+                    changes.push(change);
+                }
+            }
+        }
+        changes.retain(|change| change.before != change.after);
+
+        if !changes.is_empty() {
+            self.undo.push_back(Edit { label: label.to_string(), changes });
+        }
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        self.trim();
+    }
+
+    /// The edit to revert, moved over to the redo stack
+    pub fn undo(&mut self) -> Option<Edit> {
+        let edit = self.undo.pop_back()?;
+        self.redo.push(edit.clone());
+        Some(edit)
+    }
+
+    /// The edit to apply again, moved back to the undo stack
+    pub fn redo(&mut self) -> Option<Edit> {
+        let edit = self.redo.pop()?;
+        self.undo.push_back(edit.clone());
+        Some(edit)
+    }
+
+    pub fn undo_labels(&self) -> impl Iterator<Item = &str> {
+        self.undo.iter().rev().map(|edit| edit.label.as_str())
+    }
+
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /*
+    Little endian, after the depth:
+
+        u32 count, then per edit of the undo stack (oldest first)
+            u32 label length, label bytes
+            u32 change count, then per change: i32 x, y, z, u32 before, after
+        the same for the redo stack
+
+    Pending changes are committed first, so they are saved as well.
+    */
+    pub fn write(&mut self, out: &mut impl Write) -> io::Result<()> {
+        self.commit("edit");
+
+        write_u32(out, self.depth as u32)?;
+        for stack in [self.undo.iter().collect::<Vec<_>>(), self.redo.iter().collect()] {
+            write_u32(out, stack.len() as u32)?;
+            for edit in stack {
+                write_u32(out, edit.label.len() as u32)?;
+                out.write_all(edit.label.as_bytes())?;
+
+                write_u32(out, edit.changes.len() as u32)?;
+                for change in &edit.changes {
+                    for v in change.pos.to_array() {
+                        write_u32(out, v as u32)?;
+                    }
+                    write_u32(out, change.before as u32)?;
+                    write_u32(out, change.after as u32)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read(input: &mut impl Read) -> io::Result<Self> {
+        let mut history = Self::new();
+        history.depth = read_u32(input)? as usize;
+
+        let mut stacks = [vec![], vec![]];
+        for stack in &mut stacks {
+            for _ in 0..read_u32(input)? {
+                /* not allocated up front, a broken length would ask for gigabytes */
+                let len = read_u32(input)? as usize;
+                let mut label = vec![];
+                input.by_ref().take(len as u64).read_to_end(&mut label)?;
+                if label.len() != len {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                let label = String::from_utf8(label).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                let mut changes = vec![];
+                for _ in 0..read_u32(input)? {
+                    let pos = ivec3(read_u32(input)? as i32, read_u32(input)? as i32, read_u32(input)? as i32);
+                    let before = read_u32(input)? as usize;
+                    let after = read_u32(input)? as usize;
+                    changes.push(VoxelChange { pos, before, after });
+                }
+
+                stack.push(Edit { label, changes });
+            }
+        }
+
+        let [undo, redo] = stacks;
+        history.undo = undo.into();
+        history.redo = redo;
+        history.trim();
+
+        Ok(history)
+    }
+}
+
+pub fn write_u32(out: &mut impl Write, v: u32) -> io::Result<()> {
+    out.write_all(&v.to_le_bytes())
+}
+
+pub fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use glam::ivec3;
+
+    use super::{EditHistory, VoxelChange};
+
+    #[test]
+    fn commit_keeps_the_first_before_and_the_last_after() {
+        let mut history = EditHistory::new();
+        let (a, b) = (ivec3(1, 2, 3), ivec3(-4, 5, 6));
+
+        history.record(a, 1, 0);
+        history.record(b, 0, 2);
+        history.record(a, 0, 3);
+        /* back to what it was, nothing to undo */
+        history.record(b, 2, 0);
+        history.commit("brush");
+
+        let edit = history.undo().unwrap();
+        assert_eq!(edit.label, "brush");
+        assert_eq!(edit.changes, [VoxelChange { pos: a, before: 1, after: 3 }]);
+
+        history.commit("nothing");
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn written_history_reads_the_same() {
+        let mut history = EditHistory::new();
+        for (i, label) in ["place", "brush", "paste"].into_iter().enumerate() {
+            history.record(ivec3(i as i32, -1, 2), 0, i + 1);
+            history.commit(label);
+        }
+        /* committed by `write` */
+        history.record(ivec3(7, 7, 7), 1, 0);
+        history.undo();
+        assert_eq!(history.redo_len(), 1);
+
+        let mut bytes = vec![];
+        history.write(&mut bytes).unwrap();
+        let mut read = EditHistory::read(&mut Cursor::new(&bytes)).unwrap();
+
+        assert_eq!(read.depth, history.depth);
+        assert_eq!(read.undo, history.undo);
+        assert_eq!(read.redo, history.redo);
+        assert_eq!(read.undo().unwrap().label, "edit");
+    }
+
+    #[test]
+    fn reading_keeps_only_depth_edits_and_rejects_truncated_files() {
+        let mut history = EditHistory::new();
+        for i in 0..5 {
+            history.record(ivec3(i, 0, 0), 0, 1);
+            history.commit("place");
+        }
+
+        let mut bytes = vec![];
+        history.write(&mut bytes).unwrap();
+        /* a depth lowered by hand in the file */
+        bytes[..4].copy_from_slice(&2u32.to_le_bytes());
+
+        let read = EditHistory::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(read.undo.len(), 2);
+        assert_eq!(read.undo.back(), history.undo.back());
+
+        /* a label claiming to be 4 GB long */
+        let mut broken = bytes[..8].to_vec();
+        broken.extend_from_slice(&u32::MAX.to_le_bytes());
+        broken.extend_from_slice(b"place");
+        assert!(EditHistory::read(&mut Cursor::new(&broken)).is_err());
+        assert!(EditHistory::read(&mut Cursor::new(&bytes[..bytes.len() - 1])).is_err());
+    }
+}
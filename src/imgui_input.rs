@@ -0,0 +1,74 @@
+use chaos_vk::imgui_renderer::ImGui;
+use imgui::Key;
+use winit::event::{ElementState, KeyboardInput, ModifiersState, VirtualKeyCode};
+
+/*
+chaos_vk's `ImGui` only gets the mouse, this hands it the keyboard too so
+text fields (like the schematic file name) can be typed in. Characters come
+from `ReceivedCharacter`, the keys are only there to move around and edit.
+*/
+pub trait ImGuiKeyboard {
+    fn on_key(&mut self, input: KeyboardInput);
+    fn on_character(&mut self, character: char);
+    fn on_modifiers(&mut self, modifiers: ModifiersState);
+}
+
+impl ImGuiKeyboard for ImGui {
+    fn on_key(&mut self, input: KeyboardInput) {
+        if let Some(key) = input.virtual_keycode.and_then(imgui_key) {
+            self.ctx.io_mut().add_key_event(key, input.state == ElementState::Pressed);
+        }
+    }
+
+    fn on_character(&mut self, character: char) {
+        /* backspace, enter... come as keys */
+        if !character.is_control() {
+            self.ctx.io_mut().add_input_character(character);
+        }
+    }
+
+    fn on_modifiers(&mut self, modifiers: ModifiersState) {
+        let io = self.ctx.io_mut();
+        io.add_key_event(Key::ModCtrl, modifiers.ctrl());
+        io.add_key_event(Key::ModShift, modifiers.shift());
+        io.add_key_event(Key::ModAlt, modifiers.alt());
+        io.add_key_event(Key::ModSuper, modifiers.logo());
+    }
+}
+
+/// The keys text fields use, letters for the Ctrl shortcuts
+fn imgui_key(key: VirtualKeyCode) -> Option<Key> {
+    use VirtualKeyCode as K;
+
+    Some(match key {
+        K::Tab => Key::Tab,
+        K::Left => Key::LeftArrow,
+        K::Right => Key::RightArrow,
+        K::Up => Key::UpArrow,
+        K::Down => Key::DownArrow,
+        K::PageUp => Key::PageUp,
+        K::PageDown => Key::PageDown,
+        K::Home => Key::Home,
+        K::End => Key::End,
+        K::Insert => Key::Insert,
+        K::Delete => Key::Delete,
+        K::Back => Key::Backspace,
+        K::Space => Key::Space,
+        K::Return => Key::Enter,
+        K::NumpadEnter => Key::KeypadEnter,
+        K::Escape => Key::Escape,
+        K::LControl => Key::LeftCtrl,
+        K::RControl => Key::RightCtrl,
+        K::LShift => Key::LeftShift,
+        K::RShift => Key::RightShift,
+        K::LAlt => Key::LeftAlt,
+        K::RAlt => Key::RightAlt,
+        K::A => Key::A,
+        K::C => Key::C,
+        K::V => Key::V,
+        K::X => Key::X,
+        K::Y => Key::Y,
+        K::Z => Key::Z,
+        _ => return None,
+    })
+}
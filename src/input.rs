@@ -0,0 +1,465 @@
+use std::{collections::{HashMap, HashSet}, fmt, fs, io, path::Path};
+
+use bevy_ecs::system::Resource;
+use imgui::Ui;
+use winit::event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode};
+
+/// Read at startup and written by the "save" button of the bindings window
+pub const BINDINGS_FILE: &str = "keybindings.cfg";
+
+/*
+Keys are never checked directly, everything goes through named actions. A
+subsystem registers the actions it wants with their default keys, and the
+bindings file can then move them to other keys:
+
+    # action = key, key, ...
+    move_forward = W, Up
+    run_script = F5
+
+Key names are the ones of winit's `VirtualKeyCode`, mouse buttons are
+`MouseLeft`, `MouseRight` and `MouseMiddle`. Keys can be combined with
+modifiers held before them: `undo = LControl+Z`. Several actions can share a
+key, e.g. space is both `move_up` when flying and `jump` when walking.
+*/
+#[derive(Resource)]
+pub struct Bindings {
+    /// in registration order, for the UI
+    actions: Vec<String>,
+    keys: HashMap<String, Vec<Binding>>,
+    /// from the bindings file, applied when the action gets registered
+    overrides: HashMap<String, Vec<Binding>>,
+
+    held: HashSet<Button>,
+    /// action waiting for a key to be added to it in the UI
+    capturing: Option<String>,
+
+    pub show_ui: bool,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        Self {
+            actions: vec![],
+            keys: HashMap::new(),
+            overrides: HashMap::new(),
+            held: HashSet::new(),
+            capturing: None,
+            show_ui: false,
+        }
+    }
+
+    /// Bindings from `path` replace the defaults of the actions they name,
+    /// registered already or not. Lines that can't be read are skipped with a warning
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let src = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+
+        for (line_i, line) in src.lines().enumerate() {
+            if let Err(e) = self.load_line(line) {
+                println!("{}:{}: {e}, skipped", path.display(), line_i + 1);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+
+        let (action, keys) = line
+            .split_once('=')
+            .ok_or("expected `action = key, key`")?;
+
+        let keys = keys
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| binding_from_name(name).ok_or(format!("unknown key {name}")))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let action = action.trim().to_string();
+        if self.keys.contains_key(&action) {
+            self.keys.insert(action.clone(), keys.clone());
+        }
+        self.overrides.insert(action, keys);
+
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = String::from("# action = key, key, ...\n");
+
+        for action in &self.actions {
+            let keys: Vec<String> = self.keys[action].iter().map(|key| key.to_string()).collect();
+            out += &format!("{action} = {}\n", keys.join(", "));
+        }
+
+        fs::write(path, out)
+    }
+
+    /// Adds an action, unless it exists already. `defaults` are only used
+    /// if the bindings file doesn't mention it
+    pub fn register<B: Into<Binding> + Clone>(&mut self, action: &str, defaults: &[B]) {
+        if self.keys.contains_key(action) {
+            return;
+        }
+
+        let keys = self.overrides
+            .get(action)
+            .cloned()
+            .unwrap_or(defaults.iter().map(|key| key.clone().into()).collect());
+
+        self.actions.push(action.to_string());
+        self.keys.insert(action.to_string(), keys);
+    }
+
+    /// Keeps track of the held keys, returns the actions this press triggered
+    pub fn handle_key(&mut self, input: KeyboardInput) -> Vec<String> {
+        let Some(key) = input.virtual_keycode else {
+            return vec![];
+        };
+
+        self.handle_button(Button::Key(key), input.state)
+    }
+
+    /// Same as `handle_key`, for mouse buttons
+    pub fn handle_mouse(&mut self, button: MouseButton, state: ElementState) -> Vec<String> {
+        self.handle_button(Button::Mouse(button), state)
+    }
+
+    fn handle_button(&mut self, key: Button, state: ElementState) -> Vec<String> {
+        if state == ElementState::Released {
+            self.held.remove(&key);
+
+            /* a modifier let go of without pressing anything else is bound on its own */
+            if self.capturing.is_some() && is_modifier(key) {
+                self.capture(key);
+            }
+            return vec![];
+        }
+
+        /* key repeat */
+        if !self.held.insert(key) {
+            return vec![];
+        }
+
+        if self.capturing.is_some() {
+            if !is_modifier(key) {
+                self.capture(key);
+            }
+            return vec![];
+        }
+
+        self.actions
+            .iter()
+            .filter(|action| self.keys[*action].iter().any(|binding| binding.button == key && self.modifiers_held(binding) && !self.other_modifiers_held(binding)))
+            .cloned()
+            .collect()
+    }
+
+    /// Binds `key` to the action being captured, combined with the modifiers held
+    fn capture(&mut self, key: Button) {
+        /* it couldn't be saved to the bindings file, keep waiting for another one */
+        if !has_name(key) {
+            return;
+        }
+
+        let Some(action) = self.capturing.take() else {
+            return;
+        };
+
+        let mut modifiers: Vec<Button> = self.held.iter().copied().filter(|held| *held != key && is_modifier(*held)).collect();
+        modifiers.sort_by_key(|modifier| modifier.to_string());
+        let binding = Binding { modifiers, button: key };
+
+        let keys = self.keys.get_mut(&action).unwrap();
+        if key != Button::Key(VirtualKeyCode::Escape) && !keys.contains(&binding) {
+            keys.push(binding);
+        }
+    }
+
+    fn modifiers_held(&self, binding: &Binding) -> bool {
+        binding.modifiers.iter().all(|modifier| self.held.contains(modifier))
+    }
+
+    /// Ctrl or Alt held without being part of the binding, so that `V` doesn't
+    /// go off along with `LControl+V`. Shift is left out, it is held to
+    /// sprint, and so are clicks, to dig while crouching
+    fn other_modifiers_held(&self, binding: &Binding) -> bool {
+        use VirtualKeyCode::*;
+        if matches!(binding.button, Button::Mouse(_)) {
+            return false;
+        }
+
+        self.held.iter().any(|held| {
+            matches!(held, Button::Key(LControl | RControl | LAlt | RAlt)) && !binding.modifiers.contains(held)
+        })
+    }
+
+    /// Whether any key of the action is held down, with its modifiers. A
+    /// modifier held for a chord doesn't count, so `LControl+Z` doesn't also
+    /// move down, while `LControl` and `W` still move down and forward
+    pub fn is_down(&self, action: &str) -> bool {
+        self.keys
+            .get(action)
+            .map_or(false, |keys| keys.iter().any(|key| self.held.contains(&key.button) && self.modifiers_held(key) && !self.in_chord(key.button)))
+    }
+
+    /// Whether `modifier` is one of the modifiers of a binding that is held down
+    fn in_chord(&self, modifier: Button) -> bool {
+        is_modifier(modifier) && self.keys.values().flatten().any(|binding| {
+            binding.modifiers.contains(&modifier) && self.held.contains(&binding.button) && self.modifiers_held(binding)
+        })
+    }
+
+    pub fn keys(&self, action: &str) -> &[Binding] {
+        self.keys.get(action).map_or(&[], |keys| keys.as_slice())
+    }
+
+    /// Click a key to unbind it, click `+` and press a key to add one
+    pub fn ui(&mut self, ui: &Ui) {
+        if !self.show_ui {
+            return;
+        }
+
+        ui.window("key bindings").build(|| {
+            for action in &self.actions {
+                ui.text(action);
+
+                let keys = self.keys.get_mut(action).unwrap();
+                let mut removed = None;
+                for (i, key) in keys.iter().enumerate() {
+                    ui.same_line();
+                    if ui.button(format!("{key}##{action}{i}")) {
+                        removed = Some(i);
+                    }
+                }
+                if let Some(i) = removed {
+                    keys.remove(i);
+                }
+
+                ui.same_line();
+                if self.capturing.as_ref() == Some(action) {
+                    ui.text("press a key or click (escape cancels)");
+                } else if ui.button(format!("+##{action}")) {
+                    self.capturing = Some(action.clone());
+                }
+            }
+
+            ui.separator();
+            if ui.button("save") {
+                match self.save(Path::new(BINDINGS_FILE)) {
+                    Ok(()) => println!("saved key bindings to {BINDINGS_FILE}"),
+                    Err(e) => println!("failed to save key bindings: {e}"),
+                }
+            }
+        });
+    }
+}
+
+/// Something that can be bound to an action
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+impl From<VirtualKeyCode> for Button {
+    fn from(key: VirtualKeyCode) -> Self {
+        Button::Key(key)
+    }
+}
+
+impl From<MouseButton> for Button {
+    fn from(button: MouseButton) -> Self {
+        Button::Mouse(button)
+    }
+}
+
+/// A button, pressed while holding some others (usually Ctrl, Shift or Alt)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub modifiers: Vec<Button>,
+    pub button: Button,
+}
+
+impl Binding {
+    pub fn with(modifier: impl Into<Button>, button: impl Into<Button>) -> Self {
+        Self {
+            modifiers: vec![modifier.into()],
+            button: button.into(),
+        }
+    }
+}
+
+impl<B: Into<Button>> From<B> for Binding {
+    fn from(button: B) -> Self {
+        Self {
+            modifiers: vec![],
+            button: button.into(),
+        }
+    }
+}
+
+/// `LControl+Z`
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{modifier}+")?;
+        }
+        write!(f, "{}", self.button)
+    }
+}
+
+fn is_modifier(button: Button) -> bool {
+    use VirtualKeyCode::*;
+    matches!(button, Button::Key(LShift | RShift | LControl | RControl | LAlt | RAlt))
+}
+
+/// The name used in the bindings file
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Button::Key(key) => write!(f, "{key:?}"),
+            Button::Mouse(MouseButton::Left) => write!(f, "MouseLeft"),
+            Button::Mouse(MouseButton::Right) => write!(f, "MouseRight"),
+            Button::Mouse(MouseButton::Middle) => write!(f, "MouseMiddle"),
+            Button::Mouse(MouseButton::Other(n)) => write!(f, "Mouse{n}"),
+        }
+    }
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        &[$((stringify!($key), VirtualKeyCode::$key)),*]
+    };
+}
+
+/// Keys that can be named in the bindings file
+const KEY_NAMES: &[(&str, VirtualKeyCode)] = key_names![
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Escape, Tab, Space, Return, Back, Insert, Delete, Home, End, PageUp, PageDown,
+    Left, Up, Right, Down,
+    LShift, RShift, LControl, RControl, LAlt, RAlt,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    Minus, Equals, Comma, Period, Slash, Backslash, Semicolon, Apostrophe, Grave, LBracket, RBracket,
+];
+
+/// Whether `button` can be written to the bindings file and read back
+fn has_name(button: Button) -> bool {
+    match button {
+        Button::Key(key) => KEY_NAMES.iter().any(|(_, named)| *named == key),
+        Button::Mouse(_) => true,
+    }
+}
+
+pub fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    KEY_NAMES
+        .iter()
+        .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+        .map(|(_, key)| *key)
+}
+
+/// A key name or `MouseLeft`, `MouseRight`, `MouseMiddle`, `Mouse<n>`
+pub fn button_from_name(name: &str) -> Option<Button> {
+    if let Some(key) = key_from_name(name) {
+        return Some(Button::Key(key));
+    }
+
+    let button = name.get(..5).filter(|m| m.eq_ignore_ascii_case("mouse")).map(|_| &name[5..])?;
+    let button = match button.to_ascii_lowercase().as_str() {
+        "left" => MouseButton::Left,
+        "right" => MouseButton::Right,
+        "middle" => MouseButton::Middle,
+        n => MouseButton::Other(n.parse().ok()?),
+    };
+
+    Some(Button::Mouse(button))
+}
+
+/// Button names joined by `+`, the last one being the button that triggers
+pub fn binding_from_name(name: &str) -> Option<Binding> {
+    let mut buttons = name
+        .split('+')
+        .map(|name| button_from_name(name.trim()))
+        .collect::<Option<Vec<_>>>()?;
+    let button = buttons.pop()?;
+
+    Some(Binding { modifiers: buttons, button })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(bindings: &mut Bindings, button: impl Into<Button>) -> Vec<String> {
+        let button = button.into();
+        let actions = bindings.handle_button(button, ElementState::Pressed);
+        bindings.handle_button(button, ElementState::Released);
+        actions
+    }
+
+    #[test]
+    fn every_named_key_round_trips() {
+        for (_, key) in KEY_NAMES {
+            let binding = Binding::with(VirtualKeyCode::LControl, *key);
+            assert_eq!(binding_from_name(&binding.to_string()), Some(binding));
+        }
+
+        for button in [MouseButton::Left, MouseButton::Right, MouseButton::Middle, MouseButton::Other(4)] {
+            assert_eq!(button_from_name(&Button::Mouse(button).to_string()), Some(Button::Mouse(button)));
+        }
+    }
+
+    #[test]
+    fn capture_waits_for_a_key_with_a_name() {
+        let mut bindings = Bindings::new();
+        bindings.register("jump", &[VirtualKeyCode::Space]);
+        bindings.capturing = Some("jump".to_string());
+
+        press(&mut bindings, VirtualKeyCode::NumpadAdd);
+        assert_eq!(bindings.keys("jump").len(), 1);
+
+        press(&mut bindings, VirtualKeyCode::J);
+        assert_eq!(bindings.keys("jump"), [Binding::from(VirtualKeyCode::Space), Binding::from(VirtualKeyCode::J)]);
+        assert_eq!(press(&mut bindings, VirtualKeyCode::J), ["jump"]);
+    }
+
+    #[test]
+    fn modifiers_held_for_a_chord_are_not_down() {
+        let mut bindings = Bindings::new();
+        bindings.register("move_down", &[VirtualKeyCode::LControl]);
+        bindings.register("move_forward", &[VirtualKeyCode::W]);
+        bindings.register("undo", &[Binding::with(VirtualKeyCode::LControl, VirtualKeyCode::Z)]);
+
+        bindings.handle_button(VirtualKeyCode::LControl.into(), ElementState::Pressed);
+        assert!(bindings.is_down("move_down"));
+
+        assert_eq!(bindings.handle_button(VirtualKeyCode::Z.into(), ElementState::Pressed), ["undo"]);
+        assert!(!bindings.is_down("move_down"));
+
+        bindings.handle_button(VirtualKeyCode::Z.into(), ElementState::Released);
+        bindings.handle_button(VirtualKeyCode::W.into(), ElementState::Pressed);
+        assert!(bindings.is_down("move_down"));
+        assert!(bindings.is_down("move_forward"));
+    }
+
+    #[test]
+    fn bad_lines_are_skipped() {
+        let mut bindings = Bindings::new();
+        bindings.register("jump", &[VirtualKeyCode::Space]);
+        bindings.register("crouch", &[VirtualKeyCode::C]);
+
+        assert!(bindings.load_line("jump = Space, NotAKey").is_err());
+        assert!(bindings.load_line("crouch LControl").is_err());
+        bindings.load_line("crouch = LControl").unwrap();
+        bindings.load_line("# crouch = X").unwrap();
+
+        assert_eq!(bindings.keys("jump"), [Binding::from(VirtualKeyCode::Space)]);
+        assert_eq!(bindings.keys("crouch"), [Binding::from(VirtualKeyCode::LControl)]);
+    }
+}
@@ -0,0 +1,111 @@
+use bevy_ecs::system::{Commands, Resource};
+use chaos_vk::graphics::camera::Camera;
+use glam::{vec3, Vec3};
+
+use crate::shaders::fs;
+
+/// Has to match the size of `point_lights` in `shaders::fs`
+pub const MAX_POINT_LIGHTS: usize = 32;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub pos: Vec3,
+    pub color: Vec3,
+    pub radius: f32,
+}
+
+/// Spotlight attached to the camera
+#[derive(Clone, Copy, Debug)]
+pub struct Headlamp {
+    pub enabled: bool,
+    pub color: Vec3,
+    pub range: f32,
+    /* half angles of the cone, in degrees */
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LightCommand {
+    Add(PointLight),
+    Clear,
+}
+
+#[derive(Resource)]
+pub struct Lights {
+    pub headlamp: Headlamp,
+    pub point_lights: Vec<PointLight>,
+    pub ambient: f32,
+}
+
+impl Lights {
+    pub fn new() -> Self {
+        Self {
+            headlamp: Headlamp {
+                enabled: true,
+                color: vec3(1.0, 0.95, 0.8),
+                range: 150.0,
+                inner_angle: 15.0,
+                outer_angle: 30.0,
+            },
+            point_lights: vec![],
+            ambient: 0.15,
+        }
+    }
+
+    pub fn add(&mut self, light: PointLight) {
+        self.point_lights.push(light);
+    }
+
+    pub fn clear(&mut self) {
+        self.point_lights.clear();
+    }
+
+    pub fn apply(&mut self, command: LightCommand) {
+        match command {
+            LightCommand::Add(light) => self.add(light),
+            LightCommand::Clear => self.clear(),
+        }
+    }
+
+    /// Only the `MAX_POINT_LIGHTS` lights closest to the camera make it to the gpu
+    pub fn get_ubo_data(&self, camera: &Camera) -> fs::Lights {
+        let mut closest = self.point_lights.clone();
+        closest.sort_by(|a, b| {
+            a.pos.distance_squared(camera.pos)
+                .total_cmp(&b.pos.distance_squared(camera.pos))
+        });
+
+        let mut point_lights = [fs::PointLight {
+            pos_radius: [0.0; 4],
+            color: [0.0; 4],
+        }; MAX_POINT_LIGHTS];
+
+        for (i, light) in closest.iter().take(MAX_POINT_LIGHTS).enumerate() {
+            point_lights[i] = fs::PointLight {
+                pos_radius: [light.pos.x, light.pos.y, light.pos.z, light.radius],
+                color: [light.color.x, light.color.y, light.color.z, 1.0],
+            };
+        }
+
+        let lamp = &self.headlamp;
+
+        fs::Lights {
+            lamp_pos: [camera.pos.x, camera.pos.y, camera.pos.z, lamp.enabled as u32 as f32],
+            lamp_dir: [camera.front.x, camera.front.y, camera.front.z, lamp.range],
+            lamp_color: [lamp.color.x, lamp.color.y, lamp.color.z, self.ambient],
+            lamp_cone: [
+                lamp.inner_angle.to_radians().cos(),
+                lamp.outer_angle.to_radians().cos(),
+                0.0,
+                0.0,
+            ],
+            point_lights,
+            point_light_count: closest.len().min(MAX_POINT_LIGHTS) as u32,
+        }
+    }
+}
+
+pub fn startup(mut commands: Commands) {
+    commands.insert_resource(Lights::new());
+}
@@ -1,77 +1,219 @@
-use std::{fs::File, io::Read};
+use std::{cell::{Cell, RefCell}, fs::File, io::Read};
 
-use bevy_app::App;
 use glam::vec3;
-use mlua::Lua;
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+use bevy_ecs::world::World;
+use mlua::{Function, Lua};
+use winit::event::VirtualKeyCode;
 
-use crate::mesh_spawner::{SpawnCommand, SpawnCommandBuffer};
+use crate::{input::{Bindings, Button}, lua_camera::{self, SharedRenderer}, lua_world::{self, hit_table, pos_table, SharedWorld}, raycast::REACH, renderer::Renderer, script_commands::{self, ScriptQueue}, window::WindowState, world::ChunkWorld};
+
+/// Globals the engine calls every frame, if a script defined them
+const CALLBACKS: [&str; 4] = ["on_update", "on_key", "on_chunk_loaded", "on_edit"];
+
+/// Waiting for the next `update` to be handed to `on_key`
+struct KeyEvent {
+    name: String,
+    pressed: bool,
+}
 
 pub struct LuaIntegration {
     lua: Lua,
     buf: String,
+    keys: Vec<KeyEvent>,
 }
 
 impl LuaIntegration {
-    pub fn new() -> Self {
+    /// Script functions push their commands to `queue`, see `script_commands`
+    pub fn new(queue: ScriptQueue) -> Self {
         let lua = Lua::new();
+        script_commands::register(&lua, queue).unwrap();
 
-        let spawn_command_buffer: Vec<u128> = vec![];
+        Self {
+            lua,
+            buf: String::new(),
+            keys: vec![],
+        }
+    }
 
-        lua.globals().set("spawn_commands", spawn_command_buffer).unwrap();
-        let spawn_sphere = lua.create_function(|lua, (x, y, z, r): (f32, f32, f32, f32) | {
-            let command = SpawnCommand::Sphere(vec3(x, y, z), r);
-            lua.globals().set("spawn_commands", vec![command.encode()]).unwrap();
-            lua.globals().get::<&str, Vec<u128>>("spawn_commands").unwrap().push(command.encode());
-            
-            Ok(())
-        }).unwrap();
-        let clear_world = lua.create_function(|lua, ()| {
-            let command = SpawnCommand::Clear;
-            lua.globals().set("spawn_commands", vec![command.encode()]).unwrap();
-            lua.globals().get::<&str, Vec<u128>>("spawn_commands").unwrap().push(command.encode());
-            
-            Ok(())
-        }).unwrap();
+    pub fn register_actions(bindings: &mut Bindings) {
+        bindings.register("run_script", &[VirtualKeyCode::F5]);
+    }
 
-        lua.globals().set("spawn_sphere", spawn_sphere).unwrap();
-        lua.globals().set("clear_world", clear_world).unwrap();
+    /// Runs `script.lua`. Callbacks it defined before are forgotten, so
+    /// running a script without them stops the old ones
+    pub fn run_script(&mut self, world: &mut World, renderer: &mut Renderer) {
+        self.buf.clear();
+        let read = File::open("script.lua").and_then(|mut file| file.read_to_string(&mut self.buf));
+        if let Err(e) = read {
+            println!("script.lua: {e}");
+            return;
+        }
 
-        Self {
-            lua,
-            buf: String::new()
+        if let Err(e) = self.exec(self.buf.trim(), world, renderer) {
+            println!("script.lua: {e}");
         }
     }
 
-    pub fn handle_input(&mut self, input: KeyboardInput) {
-        if input.state == ElementState::Pressed {
-            if let Some(keycode) = input.virtual_keycode {
-                match keycode {
-                    VirtualKeyCode::F5 => {
-                        let mut file = File::open("script.lua").unwrap();
-                        file.read_to_string(&mut self.buf).unwrap();
+    fn exec(&self, src: &str, world: &mut World, renderer: &mut Renderer) -> mlua::Result<()> {
+        let globals = self.lua.globals();
+        for name in CALLBACKS {
+            globals.raw_remove(name)?;
+        }
+
+        with_api(&self.lua, world, renderer, |lua| lua.load(src).exec())
+    }
 
-                        let chunk = self.lua.load(self.buf.as_str().trim());
-                        chunk.exec().ok();
+    /// Keeps a key or mouse button for `on_key`
+    pub fn key(&mut self, button: Button, pressed: bool) {
+        self.keys.push(KeyEvent { name: button.to_string(), pressed });
+    }
 
-                        self.buf.clear();
-                    }
-                    _ => ()
+    /*
+    Calls the callbacks of the last script:
 
-                }
+        on_update(dt)
+        on_key(name, pressed)          name as in the key bindings, "W", "MouseLeft"...
+        on_chunk_loaded({ i, j, k })
+        on_edit({ min = { x, y, z }, max = { x, y, z } })
+
+    A callback that fails is reported and removed, the others keep going. Has
+    to run before `ChunkWorld::update`, which drops the events of the frame.
+    Edits the callbacks make don't come back to `on_edit`, so it can't call
+    itself forever: the ones made through `world` are dropped below, the queued
+    ones (`brush`, `carve_sphere`...) are kept out by `script_commands::apply`
+    */
+    pub fn update(&mut self, world: &mut World, renderer: &mut Renderer, dt: f32) {
+        let keys = std::mem::take(&mut self.keys);
+
+        let globals = self.lua.globals();
+        let defined = CALLBACKS.iter().any(|name| globals.contains_key(*name).unwrap_or(false));
+        if !defined {
+            return;
+        }
+
+        let events = world.resource_mut::<ChunkWorld>().take_events();
+
+        let result = with_api(&self.lua, world, renderer, |lua| {
+            call(lua, "on_update", dt);
+            for key in keys {
+                call(lua, "on_key", (key.name, key.pressed));
+            }
+            for (i, j, k) in events.loaded {
+                call(lua, "on_chunk_loaded", [i, j, k]);
             }
+            if let Some((min, max)) = events.edited {
+                let region = lua.create_table()?;
+                region.set("min", pos_table(lua, min)?)?;
+                region.set("max", pos_table(lua, max)?)?;
+                call(lua, "on_edit", region);
+            }
+
+            Ok(())
+        });
+
+        if let Err(e) = result {
+            println!("script.lua: {e}");
         }
+
+        world.resource_mut::<ChunkWorld>().take_events();
     }
+}
+
+/// Calls the global `name` if it is set, removing it if it fails
+fn call<'lua>(lua: &'lua Lua, name: &str, args: impl mlua::IntoLuaMulti<'lua>) {
+    let globals = lua.globals();
+    let result = match globals.get::<_, Option<Function>>(name) {
+        Ok(Some(callback)) => callback.call::<_, ()>(args),
+        Ok(None) => return,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = result {
+        println!("script.lua: {name} failed and was removed: {e}");
+        globals.raw_remove(name).unwrap();
+    }
+}
+
+/*
+Runs `f` with the functions that borrow the engine, like `world` and `camera`,
+set up. They only live as long as `f` runs, so scripts have to go through the
+globals again every time instead of keeping them around.
+*/
+fn with_api<R>(lua: &Lua, world: &mut World, renderer: &mut Renderer, f: impl FnOnce(&Lua) -> mlua::Result<R>) -> mlua::Result<R> {
+    let fov = Cell::new(world.resource::<WindowState>().fov_y);
+    let chunkworld: SharedWorld = RefCell::new(world.resource_mut::<ChunkWorld>());
+    let renderer: SharedRenderer = RefCell::new(renderer);
+
+    let result = lua.scope(|scope| {
+        let globals = lua.globals();
+
+        globals.set("world", lua_world::world_table(lua, scope, &chunkworld)?)?;
+        globals.set("camera", lua_camera::camera_table(lua, scope, &renderer, &fov)?)?;
+
+        /* raycast(x, y, z, dx, dy, dz, [max_distance]) */
+        globals.set("raycast", scope.create_function(|lua, (x, y, z, dx, dy, dz, max): (f32, f32, f32, f32, f32, f32, Option<f32>)| {
+            let hit = lua_world::borrow(&chunkworld)?.raycast(vec3(x, y, z), vec3(dx, dy, dz), max.unwrap_or(REACH));
+            hit.map(|hit| hit_table(lua, &hit)).transpose()
+        })?)?;
+
+        /* the voxel under the crosshair */
+        globals.set("look_target", scope.create_function(|lua, max: Option<f32>| {
+            let camera = lua_camera::borrow(&renderer)?.camera;
+            let hit = lua_world::borrow(&chunkworld)?.raycast(camera.pos, camera.front, max.unwrap_or(REACH));
+            hit.map(|hit| hit_table(lua, &hit)).transpose()
+        })?)?;
+
+        f(lua)
+    });
 
-    pub fn update(&mut self, app: &mut App) {
-        let mut spawn_commands = app.world_mut().get_resource_mut::<SpawnCommandBuffer>().unwrap();
-        let lua_spawn_commands = self.lua.globals().get::<&str, Vec<u128>>("spawn_commands").unwrap();
-        spawn_commands.commands = lua_spawn_commands
-            .iter()
-            .map(|v| SpawnCommand::decode(*v))
-            .collect();
-        
-        let clear: Vec<u128> = vec![];
-        self.lua.globals().set("spawn_commands", clear).unwrap();
+    /* whatever the script changed through `world` is one undo step */
+    chunkworld.into_inner().history.commit("script");
+    world.resource_mut::<WindowState>().fov_y = fov.get();
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::world::World;
+    use glam::ivec3;
+
+    use crate::{renderer::Renderer, script_commands::{self, ScriptQueue}, window::WindowState, world::{Chunk, ChunkWorld, Voxel, CHUNK_VOLUME}};
+
+    use super::LuaIntegration;
+
+    #[test]
+    fn on_edit_is_not_called_again_for_the_edits_it_queues() {
+        let queue = ScriptQueue::default();
+        let mut lua = LuaIntegration::new(queue.clone());
+
+        let mut chunkworld = ChunkWorld::detached();
+        chunkworld.insert_chunk(Chunk::from_voxels((0, 0, 0), vec![Voxel { id: 1 }; CHUNK_VOLUME]));
+
+        let mut world = World::new();
+        world.insert_resource(chunkworld);
+        world.insert_resource(WindowState::new(1200, 900));
+        world.insert_resource(queue);
+        let mut renderer = Renderer::new();
+
+        let script = "
+            calls = 0
+            function on_edit(region)
+                calls = calls + 1
+                carve_sphere(32, 32, 32, 3)
+            end
+        ";
+        lua.exec(script, &mut world, &mut renderer).unwrap();
+
+        world.resource_mut::<ChunkWorld>().set_voxel(ivec3(1, 1, 1), Voxel { id: 0 });
+        /* the frames of the app: callbacks, then the queued commands */
+        for _ in 0..3 {
+            lua.update(&mut world, &mut renderer, 1.0 / 60.0);
+            script_commands::apply(&mut world);
+        }
+
+        let calls: u32 = lua.lua.globals().get("calls").unwrap();
+        assert_eq!(calls, 1);
+        assert!(!world.resource::<ChunkWorld>().is_solid(ivec3(32, 32, 32)));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,128 @@
+use std::cell::{Cell, RefCell, RefMut};
+
+use glam::{vec3, Vec3};
+use mlua::{Lua, Scope, Table};
+
+use crate::{camera_path::CameraMove, lua_world::to_vec3, renderer::Renderer};
+
+/// Field of view scripts can set, in degrees
+pub const FOV_RANGE: (f32, f32) = (10.0, 150.0);
+
+/// The renderer as the script functions see it, borrowed for one script run
+pub type SharedRenderer<'r> = RefCell<&'r mut Renderer>;
+
+/// Like `lua_world::borrow`
+pub fn borrow<'a, 'r>(renderer: &'a SharedRenderer<'r>) -> mlua::Result<RefMut<'a, &'r mut Renderer>> {
+    renderer.try_borrow_mut().map_err(|_| mlua::Error::RuntimeError("the camera is busy".into()))
+}
+
+fn vec_table<'lua>(lua: &'lua Lua, v: Vec3) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set("x", v.x)?;
+    table.set("y", v.y)?;
+    table.set("z", v.z)?;
+
+    Ok(table)
+}
+
+fn seconds(seconds: f32) -> mlua::Result<f32> {
+    if seconds.is_finite() && seconds >= 0.0 {
+        Ok(seconds)
+    } else {
+        Err(mlua::Error::RuntimeError(format!("bad duration: {seconds}")))
+    }
+}
+
+/*
+The `camera` table of a running script. Setting the position or rotation
+takes effect right away, while `move_to` and `wait` queue up a tour that
+plays over the next frames:
+
+    camera.move_to({ x = 0, y = -20, z = 0 }, 3)
+    camera.wait(1)
+    camera.move_to({ x = 40, y = -20, z = 0 }, 5, { x = 20, y = 0, z = 0 })
+
+Positions are tables like `world.raycast` takes. `fov` is the window's
+vertical field of view in degrees, written back once the script is done.
+*/
+pub fn camera_table<'lua, 'scope, 'r: 'scope>(
+    lua: &'lua Lua,
+    scope: &Scope<'lua, 'scope>,
+    renderer: &'scope SharedRenderer<'r>,
+    fov: &'scope Cell<f32>,
+) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+
+    table.set("get_position", scope.create_function(|lua, ()| {
+        vec_table(lua, borrow(renderer)?.camera.pos)
+    })?)?;
+
+    /* stops any scripted moves */
+    table.set("set_position", scope.create_function(|_, (x, y, z): (f32, f32, f32)| {
+        borrow(renderer)?.teleport(vec3(x, y, z));
+        Ok(())
+    })?)?;
+
+    /* yaw, pitch in degrees */
+    table.set("get_rotation", scope.create_function(|_, ()| {
+        let renderer = borrow(renderer)?;
+        Ok((renderer.camera.yaw, renderer.camera.pitch))
+    })?)?;
+
+    table.set("set_rotation", scope.create_function(|_, (yaw, pitch): (f32, f32)| {
+        borrow(renderer)?.set_rotation(yaw, pitch);
+        Ok(())
+    })?)?;
+
+    /* the direction the camera looks in */
+    table.set("get_front", scope.create_function(|lua, ()| {
+        vec_table(lua, borrow(renderer)?.camera.front)
+    })?)?;
+
+    table.set("look_at", scope.create_function(|_, (x, y, z): (f32, f32, f32)| {
+        let mut renderer = borrow(renderer)?;
+        let pos = renderer.camera.pos;
+        let front = (vec3(x, y, z) - pos).normalize_or(renderer.camera.front);
+        renderer.look(pos, front);
+        Ok(())
+    })?)?;
+
+    /* flying speed, in voxels a second */
+    table.set("get_speed", scope.create_function(|_, ()| Ok(borrow(renderer)?.camera.speed))?)?;
+    table.set("set_speed", scope.create_function(|_, speed: f32| {
+        borrow(renderer)?.camera.speed = speed.max(0.0);
+        Ok(())
+    })?)?;
+
+    table.set("get_fov", scope.create_function(|_, ()| Ok(fov.get()))?)?;
+    table.set("set_fov", scope.create_function(|_, degrees: f32| {
+        fov.set(degrees.clamp(FOV_RANGE.0, FOV_RANGE.1));
+        Ok(())
+    })?)?;
+
+    /* move_to(pos, seconds, [look_at]), after the moves already queued */
+    table.set("move_to", scope.create_function(|_, (to, time, look_at): (Table, f32, Option<Table>)| {
+        let step = CameraMove {
+            to: Some(to_vec3(&to)?),
+            seconds: seconds(time)?,
+            look_at: look_at.as_ref().map(to_vec3).transpose()?,
+        };
+        borrow(renderer)?.move_to(step);
+        Ok(())
+    })?)?;
+
+    /* stays put for a while, between moves */
+    table.set("wait", scope.create_function(|_, time: f32| {
+        borrow(renderer)?.move_to(CameraMove { to: None, seconds: seconds(time)?, look_at: None });
+        Ok(())
+    })?)?;
+
+    table.set("stop", scope.create_function(|_, ()| {
+        borrow(renderer)?.stop_moving();
+        Ok(())
+    })?)?;
+
+    table.set("is_moving", scope.create_function(|_, ()| Ok(borrow(renderer)?.moves.is_moving()))?)?;
+
+    Ok(table)
+}
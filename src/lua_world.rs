@@ -0,0 +1,153 @@
+use std::cell::{RefCell, RefMut};
+
+use bevy_ecs::world::Mut;
+use glam::{vec3, IVec3, Vec3};
+use mlua::{Lua, Scope, Table};
+
+use crate::{brush::{Brush, BrushOp, BrushShape}, cave, raycast::{RayHit, REACH}, schematic::MAX_SIZE, world::{ChunkWorld, Voxel}};
+
+/// How far the cave queries search unless told otherwise
+const SEARCH_DISTANCE: i32 = 32;
+const CHAMBER_LIMIT: usize = 100_000;
+
+/// The chunk world as the script functions see it, borrowed for one script run
+pub type SharedWorld<'w> = RefCell<Mut<'w, ChunkWorld>>;
+
+/// Errors instead of panicking if a script manages to call back into the world
+/// while it is already borrowed
+pub fn borrow<'a, 'w>(world: &'a SharedWorld<'w>) -> mlua::Result<RefMut<'a, Mut<'w, ChunkWorld>>> {
+    world.try_borrow_mut().map_err(|_| mlua::Error::RuntimeError("the world is busy".into()))
+}
+
+/// Voxel positions are floored, so any point inside of a voxel names it
+fn voxel(x: f32, y: f32, z: f32) -> IVec3 {
+    vec3(x, y, z).floor().as_ivec3()
+}
+
+/// `{ x = 1, y = 2, z = 3 }` or `{ 1, 2, 3 }`
+pub fn to_vec3(table: &Table) -> mlua::Result<Vec3> {
+    match table.get::<_, Option<f32>>("x")? {
+        Some(x) => Ok(vec3(x, table.get("y")?, table.get("z")?)),
+        None => Ok(vec3(table.get(1)?, table.get(2)?, table.get(3)?)),
+    }
+}
+
+pub fn pos_table<'lua>(lua: &'lua Lua, pos: IVec3) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set("x", pos.x)?;
+    table.set("y", pos.y)?;
+    table.set("z", pos.z)?;
+
+    Ok(table)
+}
+
+/// `{ x, y, z, nx, ny, nz, distance, chunk = { i, j, k }, index }`
+pub fn hit_table<'lua>(lua: &'lua Lua, hit: &RayHit) -> mlua::Result<Table<'lua>> {
+    let table = pos_table(lua, hit.voxel)?;
+    table.set("nx", hit.normal.x)?;
+    table.set("ny", hit.normal.y)?;
+    table.set("nz", hit.normal.z)?;
+    table.set("distance", hit.distance)?;
+    table.set("chunk", [hit.key.0, hit.key.1, hit.key.2])?;
+    table.set("index", hit.index)?;
+
+    Ok(table)
+}
+
+/*
+The `world` table of a running script. Unlike the queued commands, these read
+and change `ChunkWorld` right away, so a script can look at what it just did:
+
+    world.set_voxel(0, 0, 0, 2)
+    print(world.get_voxel(0, 0, 0)) -- 2
+
+Edits go to the undo history like any other, everything a script run changes
+being one step.
+*/
+pub fn world_table<'lua, 'scope, 'w: 'scope>(
+    lua: &'lua Lua,
+    scope: &Scope<'lua, 'scope>,
+    world: &'scope SharedWorld<'w>,
+) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+
+    /* material id, nil if the chunk isn't loaded */
+    table.set("get_voxel", scope.create_function(|_, (x, y, z): (f32, f32, f32)| {
+        Ok(borrow(world)?.voxel_at(voxel(x, y, z)).map(|voxel| voxel.id))
+    })?)?;
+
+    /* false if the chunk isn't loaded */
+    table.set("set_voxel", scope.create_function(|_, (x, y, z, id): (f32, f32, f32, usize)| {
+        Ok(borrow(world)?.set_voxel(voxel(x, y, z), Voxel { id }))
+    })?)?;
+
+    /* both corners included, returns how many voxels were loaded to be filled */
+    table.set("fill_box", scope.create_function(|_, (x1, y1, z1, x2, y2, z2, id): (f32, f32, f32, f32, f32, f32, usize)| {
+        let (a, b) = (voxel(x1, y1, z1), voxel(x2, y2, z2));
+        let (min, max) = (a.min(b), a.max(b));
+        /* as big as a paste can be, for the same reasons */
+        if (max - min).max_element() >= MAX_SIZE {
+            return Err(mlua::Error::RuntimeError(format!("fill_box is limited to {MAX_SIZE} voxels a side")));
+        }
+
+        let mut world = borrow(world)?;
+        let mut filled = 0;
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    if world.set_voxel(IVec3::new(x, y, z), Voxel { id }) {
+                        filled += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(filled)
+    })?)?;
+
+    /* returns how many voxels were removed */
+    table.set("carve_sphere", scope.create_function(|_, (x, y, z, r): (f32, f32, f32, f32)| {
+        let brush = Brush { shape: BrushShape::Sphere, op: BrushOp::Subtract, radius: r, ..Default::default() };
+        let mut world = borrow(world)?;
+        Ok(brush.apply(&mut world, vec3(x, y, z)))
+    })?)?;
+
+    table.set("raycast", scope.create_function(|lua, (origin, dir, max): (Table, Table, Option<f32>)| {
+        let hit = borrow(world)?.raycast(to_vec3(&origin)?, to_vec3(&dir)?, max.unwrap_or(REACH));
+        hit.map(|hit| hit_table(lua, &hit)).transpose()
+    })?)?;
+
+    table.set("chunk_loaded", scope.create_function(|_, (i, j, k): (isize, isize, isize)| {
+        Ok(borrow(world)?.chunk(&(i, j, k)).is_some())
+    })?)?;
+
+    /* the closest air voxel, to put things inside of the cave */
+    table.set("find_cave", scope.create_function(|lua, (x, y, z, max): (f32, f32, f32, Option<i32>)| {
+        let world = borrow(world)?;
+        let solid = |pos| world.voxel_at(pos).map(|voxel| voxel.id != 0);
+
+        cave::nearest_air(&solid, voxel(x, y, z), max.unwrap_or(SEARCH_DISTANCE))
+            .map(|pos| pos_table(lua, pos))
+            .transpose()
+    })?)?;
+
+    /* how many air voxels are connected to this one, up to `limit` */
+    table.set("chamber_size", scope.create_function(|_, (x, y, z, limit): (f32, f32, f32, Option<usize>)| {
+        let world = borrow(world)?;
+        let solid = |pos| world.voxel_at(pos).map(|voxel| voxel.id != 0);
+
+        Ok(cave::chamber_size(&solid, voxel(x, y, z), limit.unwrap_or(CHAMBER_LIMIT)))
+    })?)?;
+
+    /* the floor under a point */
+    table.set("ground_below", scope.create_function(|lua, (x, y, z, max): (f32, f32, f32, Option<i32>)| {
+        let world = borrow(world)?;
+        let solid = |pos| world.voxel_at(pos).map(|voxel| voxel.id != 0);
+
+        cave::ground_below(&solid, voxel(x, y, z), max.unwrap_or(SEARCH_DISTANCE))
+            .map(|pos| pos_table(lua, pos))
+            .transpose()
+    })?)?;
+
+    Ok(table)
+}
@@ -1,19 +1,35 @@
 use std::{cell::RefCell, default, fs::File, io::Read, rc::Rc, sync::{Arc, Mutex}};
 
 use bevy_app::{App, Startup, Update};
-use bevy_ecs::{bundle::Bundle, schedule::SystemSchedule, world::World};
-use chaos_vk::{graphics::{mesh::mesh::Mesh, presenter::Presenter, utils::{instancing_pipeline, render_pass_with_depth}, vertex::{InstanceData, PosVertex}, vk::Vk}, imgui_renderer::ImGui};
+use camera_path::{CameraPath, PathState};
+use bevy_ecs::{bundle::Bundle, schedule::{IntoSystemConfigs, SystemSchedule}, world::{Mut, World}};
+use chaos_vk::{graphics::{presenter::Presenter, utils::{instancing_pipeline, render_pass_with_depth}, vertex::{InstanceData, PosVertex}, vk::Vk}, imgui_renderer::ImGui};
 use geometry::sphere;
 use glam::{vec3, Mat4, Vec3};
+use fog::{Fog, FogMode};
+use lights::Lights;
 use lua::LuaIntegration;
 use math::rand_betw;
 use mesh_spawner::{SpawnCommand, SpawnCommandBuffer};
-use renderer::{get_cmd_bufs, get_keymap, Renderer};
+use offscreen::{screenshot_path, HeadlessArgs, Offscreen};
+use pipelines::{Pipelines, Shaders};
+use raycast::REACH;
+use shader_reload::ShaderReloader;
+use input::{Bindings, Button};
+use imgui_input::ImGuiKeyboard;
+use brush::Brushes;
+use schematic::Clipboard;
+use script_commands::ScriptQueue;
+use debug::{DebugMode, DebugView};
+use renderer::{get_cmd_bufs, Renderer};
 use rlua::{chunk, Lua, RluaCompat};
-use shaders::{fs, vs};
-use vk_mod::CustomNew;
-use vulkano::{device::{Device, Features}, pipeline::{graphics::{color_blend::{ColorBlendAttachmentState, ColorBlendState}, depth_stencil::DepthStencilState, input_assembly::{InputAssemblyState, PrimitiveTopology}, multisample::MultisampleState, rasterization::RasterizationState, vertex_input::{Vertex, VertexDefinition}, viewport::{Viewport, ViewportState}, GraphicsPipelineCreateInfo}, layout::PipelineDescriptorSetLayoutCreateInfo, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo}, render_pass::{RenderPass, Subpass}, shader::ShaderModule};
+use shaders::{fs, mesh_fs, mesh_vs, vs};
+use vertex::ChunkVertex;
+use vk_mod::{CustomNew, Gpu, GpuContext};
+use vulkano::{device::{Device, Features}, format::Format, pipeline::{graphics::{color_blend::{ColorBlendAttachmentState, ColorBlendState}, depth_stencil::DepthStencilState, input_assembly::{InputAssemblyState, PrimitiveTopology}, multisample::MultisampleState, rasterization::RasterizationState, vertex_input::{Vertex, VertexDefinition}, viewport::{Viewport, ViewportState}, GraphicsPipelineCreateInfo}, layout::PipelineDescriptorSetLayoutCreateInfo, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo}, render_pass::{RenderPass, Subpass}, shader::ShaderModule};
 use winit::{dpi::PhysicalSize, event::{DeviceEvent, ElementState, Event, MouseScrollDelta, VirtualKeyCode, WindowEvent}, event_loop::{ControlFlow, EventLoop}};
+use stats::{FrameStats, StageTimings};
+use window::{WindowMode, WindowState};
 use world::{insert_chunkworld_resource, ChunkWorld};
 
 mod shaders;
@@ -21,38 +37,66 @@ mod renderer;
 mod geometry;
 mod math;
 mod mesh_spawner;
+mod lights;
+mod fog;
+mod offscreen;
+mod debug;
+mod pipelines;
+mod shader_reload;
+mod prototypes;
+mod window;
+mod stats;
+mod player;
+mod input;
+mod imgui_input;
+mod camera_path;
+mod raycast;
+mod editing;
+mod brush;
+mod history;
+mod schematic;
+mod script_commands;
+mod lua_world;
+mod lua_camera;
+mod cave;
 pub mod lua;
 pub mod world;
 pub mod chunkmesh;
 pub mod vk_mod;
 pub mod chunk_builder;
 pub mod culler;
+pub mod vertex;
+pub mod voxel_light;
 
 #[tokio::main]
 async fn main() {
+    match HeadlessArgs::from_args() {
+        Ok(Some(args)) => {
+            run_headless(args).await;
+            return;
+        }
+        Ok(None) => (),
+        Err(e) => {
+            println!("{e}");
+            std::process::exit(1);
+        }
+    }
+
     let event_loop = EventLoop::new();
     let vk = Vk::custom_new(&event_loop);
     vk.window.set_inner_size(PhysicalSize::new(1200, 900));
-    let mut app = App::new();
-
-    app
-        .add_systems(Startup, mesh_spawner::startup)
-        .add_systems(Update, mesh_spawner::update);
-
-    insert_chunkworld_resource(app.world_mut().commands(), vk.allocators.clone());
+    let gpu = Gpu::from_vk(&vk);
+    let mut app = build_app(gpu.clone(), false);
+    app.world_mut().insert_resource(WindowState::new(1200, 900));
 
     let mut renderer = Renderer::new();
     let sphere = sphere(5, 0.5, Vec3::ZERO);
-    renderer.meshes.push(Mesh::new(vk.clone(), &sphere.vertices, &sphere.indices));
-    let vs = vs::load(vk.device.clone()).unwrap();
-    let fs = fs::load(vk.device.clone()).unwrap();
+    renderer.meshes.push(gpu.mesh(&sphere.vertices, &sphere.indices));
+    let shaders = Shaders::load(vk.device.clone());
+    let mut shader_reloader = ShaderReloader::new();
     let mut presenter = Presenter::new(vk.clone());
     let rp = render_pass_with_depth(vk.clone(), Some(presenter.swapchain.clone()));
-    let mut pipeline = get_pipeline(vk.clone(), vs.clone(), fs.clone(), rp.clone(), Viewport {
-        offset: [0.0, 0.0],
-        extent: [1200.0, 900.0],
-        depth_range: 0.0..=1.0,
-    });
+    let mut pipelines = Pipelines::new(gpu.clone(), &shaders, rp.clone());
 
     let mut imgui = ImGui::new(vk.clone(), &presenter);
     presenter.window_resized = true;
@@ -61,7 +105,11 @@ async fn main() {
     let (mut cursor_x, mut cursor_y) = (0.0, 0.0);
 
     let mut buf = String::new();
-    let mut lua_integration = LuaIntegration::new();
+    let mut lua_integration = LuaIntegration::new(app.world().resource::<ScriptQueue>().clone());
+
+    let mut offscreen: Option<Offscreen> = None;
+    let mut screenshot_requested = false;
+    let mut show_history = false;
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
@@ -74,26 +122,64 @@ async fn main() {
             Event::WindowEvent { event, .. } => {
                 match event {
                     WindowEvent::KeyboardInput { input, .. } => {
-                        renderer.keymap = get_keymap(&event, &mut renderer.keymap);
-                        lua_integration.handle_input(input);
+                        imgui.on_key(input);
+
+                        /* typing in the UI doesn't trigger actions, releases still go through so keys don't stay held */
+                        if imgui.ctx.io().want_capture_keyboard && input.state == ElementState::Pressed {
+                            return;
+                        }
+
+                        if let Some(key) = input.virtual_keycode {
+                            lua_integration.key(Button::Key(key), input.state == ElementState::Pressed);
+                        }
+
+                        let actions = app.world_mut().resource_mut::<Bindings>().handle_key(input);
+
+                        for action in actions {
+                            match action.as_str() {
+                                "run_script" => lua_integration.run_script(app.world_mut(), &mut renderer),
+                                "screenshot" => screenshot_requested = true,
+                                "toggle_walk" => renderer.toggle_move_mode(),
+                                "record_path" => renderer.toggle_recording(),
+                                "play_path" => renderer.toggle_playback(),
+                                "toggle_fullscreen" => app.world_mut().resource_mut::<WindowState>().toggle_fullscreen(&vk.window),
+                                _ => editing::handle_action(&action, &renderer, app.world_mut()),
+                            }
+                        }
+                    }
+                    WindowEvent::ReceivedCharacter(character) => {
+                        imgui.on_character(character);
+                    }
+                    WindowEvent::ModifiersChanged(modifiers) => {
+                        imgui.on_modifiers(modifiers);
                     }
                     WindowEvent::CursorMoved { position, .. } => {
                         imgui.on_mouse_move(position.x as f32, position.y as f32);
                     }
                     WindowEvent::MouseInput { button, state, .. } => {
                         imgui.on_mouse_click(button, state);
+
+                        /* clicks on the UI don't edit the world */
+                        if imgui.ctx.io().want_capture_mouse && state == ElementState::Pressed {
+                            return;
+                        }
+
+                        lua_integration.key(Button::Mouse(button), state == ElementState::Pressed);
+
+                        let actions = app.world_mut().resource_mut::<Bindings>().handle_mouse(button, state);
+                        for action in actions {
+                            editing::handle_action(&action, &renderer, app.world_mut());
+                        }
                     }
                     WindowEvent::MouseWheel { delta: MouseScrollDelta::PixelDelta(pos), .. } => {
                         imgui.on_mouse_scroll(pos.x as f32, pos.y as f32);
                     }
 
+                    /* the viewport is dynamic, only the swapchain has to follow */
                     WindowEvent::Resized(size) => {
-                        renderer.camera.proj = Mat4::perspective_rh(80.0f32.to_radians(), size.width as f32/size.height as f32, 0.1, 1000.0);
-                        pipeline = get_pipeline(vk.clone(), vs.clone(), fs.clone(), rp.clone(), Viewport {
-                            offset: [0.0, 0.0],
-                            extent: size.into(),
-                            depth_range: 0.0..=1.0,
-                        });
+                        if app.world_mut().resource_mut::<WindowState>().resize(size) {
+                            presenter.window_resized = true;
+                        }
                     }
                     _ => ()
                 }
@@ -108,33 +194,180 @@ async fn main() {
             
             Event::MainEventsCleared => {
                 let now = std::time::Instant::now();
+                let mut stages = StageTimings::default();
 
                 app.update();
-
-                lua_integration.update(&mut app);
+                stages.app_update = now.elapsed();
 
                 let mut world = app.world_mut();
+
+                /* before the chunk update, which drops what happened to the world */
+                let stage = std::time::Instant::now();
+                lua_integration.update(&mut world, &mut renderer, dt);
+                stages.scripts = stage.elapsed();
+
+                let window_state = *world.resource::<WindowState>();
+                renderer.camera.proj = window_state.projection();
+
+                let stage = std::time::Instant::now();
                 let mut chunkworld = world.resource_mut::<ChunkWorld>();
-                chunkworld.update(vk.allocators.clone(), &renderer.camera);
+                chunkworld.update(vk.allocators.clone(), &renderer.camera, &window_state);
+                stages.chunk_update = stage.elapsed();
 
                 let frame = imgui.frame(&vk.window);
-                frame.text(format!("hello, world! dt: {:?}", dt*1000.0));
+                draw_crosshair(frame, window_state.extent());
+                if let Some(hit) = &renderer.target {
+                    frame.text(format!("looking at {} (face {}), {:.1} away", hit.voxel, hit.normal, hit.distance));
+                }
+                world.resource::<FrameStats>().ui(frame);
+                frame.text(format!("{:?} mode (V to switch)", renderer.move_mode));
+                match &renderer.path {
+                    PathState::Idle => frame.text("camera path: F9 records, F10 plays"),
+                    PathState::Recording { path, .. } => frame.text(format!("recording camera path, {} keyframes", path.keyframes.len())),
+                    PathState::Playing { path, time } => frame.text(format!("playing camera path {:.1}/{:.1} s", time, path.duration())),
+                }
                 frame.input_text("code", &mut buf)
                     .build();
 
+                let mut lights = world.resource_mut::<Lights>();
+                frame.checkbox("headlamp", &mut lights.headlamp.enabled);
+                frame.slider("headlamp range", 10.0, 500.0, &mut lights.headlamp.range);
+                frame.slider("ambient", 0.0, 1.0, &mut lights.ambient);
+
+                let mut fog = world.resource_mut::<Fog>();
+                let mut exponential = fog.mode == FogMode::Exponential;
+                if frame.checkbox("exponential fog", &mut exponential) {
+                    fog.mode = if exponential { FogMode::Exponential } else { FogMode::Linear };
+                }
+                let mut fog_color = fog.color.to_array();
+                if frame.color_edit3("fog color", &mut fog_color) {
+                    fog.color = fog_color.into();
+                }
+                let end = fog.end;
+                frame.slider("fog start", 0.0, end, &mut fog.start);
+                frame.slider("fog end", fog.start, Fog::max_distance(), &mut fog.end);
+                frame.slider("fog density", 0.0, 0.1, &mut fog.density);
+
+                let mut debug = world.resource_mut::<DebugView>();
+                frame.text("debug view");
+                frame.radio_button("shaded", &mut debug.mode, DebugMode::Shaded);
+                frame.radio_button("lod", &mut debug.mode, DebugMode::Lod);
+                frame.radio_button("normals", &mut debug.mode, DebugMode::Normals);
+                if pipelines.chunk_wireframe.is_some() {
+                    frame.checkbox("wireframe", &mut debug.wireframe);
+                }
+                frame.checkbox("chunk bounds", &mut debug.chunk_bounds);
+                frame.checkbox("show culled chunks", &mut debug.show_culled);
+
+                let mut window_state = world.resource_mut::<WindowState>();
+                let mut mode = window_state.mode;
+                frame.text(format!("window: {}x{}", window_state.width, window_state.height));
+                if frame.radio_button("windowed", &mut mode, WindowMode::Windowed)
+                    | frame.radio_button("borderless", &mut mode, WindowMode::Borderless)
+                    | frame.radio_button("fullscreen", &mut mode, WindowMode::Fullscreen)
+                {
+                    window_state.set_mode(&vk.window, mode);
+                }
+
+                let mut bindings = world.resource_mut::<Bindings>();
+                frame.checkbox("key bindings", &mut bindings.show_ui);
+                bindings.ui(frame);
+
+                let mut brushes = world.resource_mut::<Brushes>();
+                frame.checkbox("brushes", &mut brushes.show_ui);
+                brushes.ui(frame);
+                if std::mem::take(&mut brushes.apply_requested) {
+                    let brush = brushes.current;
+                    if let Some(hit) = &renderer.target {
+                        let mut chunkworld = world.resource_mut::<ChunkWorld>();
+                        brush.apply(&mut chunkworld, hit.point);
+                        chunkworld.history.commit("brush");
+                    }
+                }
+
+                world.resource_scope(|world, mut clipboard: Mut<Clipboard>| {
+                    frame.checkbox("clipboard", &mut clipboard.show_ui);
+                    clipboard.ui(frame, &mut world.resource_mut::<ChunkWorld>());
+                });
+
+                frame.checkbox("edit history", &mut show_history);
+                if show_history {
+                    editing::history_ui(frame, &mut world.resource_mut::<ChunkWorld>());
+                }
+
+                frame.checkbox("hot reload shaders", &mut shader_reloader.enabled);
+                if let Some(error) = &shader_reloader.error {
+                    frame.text_colored([1.0, 0.3, 0.3, 1.0], error);
+                }
+
+                frame.checkbox("secondary chunk buffers", &mut renderer.secondary_chunks);
+
+                if frame.button("spawn 10k markers") {
+                    prototypes::spawn_markers(&mut world, renderer.camera.pos, 10_000);
+                }
+
+                if frame.button("place torch") {
+                    let pos = renderer.camera.pos.floor().as_ivec3();
+                    world.resource_mut::<ChunkWorld>().place_light(pos, voxel_light::MAX_LIGHT);
+                }
+
+                /* keep the last good pipelines if the new shaders don't link */
+                if let Some(new_shaders) = shader_reloader.poll(vk.device.clone()) {
+                    match Pipelines::try_new(gpu.clone(), &new_shaders, rp.clone()) {
+                        Ok(new_pipelines) => pipelines = new_pipelines,
+                        Err(e) => shader_reloader.error = Some(e),
+                    }
+                }
+
                 presenter.recreate(vk.clone(), rp.clone());
 
                 presenter.cmd_bufs = get_cmd_bufs(
                     vk.clone(), 
+                    gpu.clone(),
                     &mut renderer, 
                     &mut imgui, 
                     &presenter, 
-                    pipeline.clone(),
+                    &pipelines,
                     &mut world,
                     rp.clone()
                 );
-                renderer.update(dt);
+
+                if screenshot_requested {
+                    screenshot_requested = false;
+
+                    let extent = world.resource::<WindowState>().extent();
+                    if offscreen.as_ref().map_or(true, |o| o.extent != extent) {
+                        offscreen = Some(Offscreen::new(gpu.clone(), presenter.swapchain.image_format(), extent));
+                    }
+
+                    let path = screenshot_path();
+                    let result = offscreen.as_ref().unwrap().screenshot(
+                        gpu.clone(),
+                        &renderer,
+                        &pipelines,
+                        &mut world,
+                        &path,
+                    );
+
+                    match result {
+                        Ok(()) => println!("saved screenshot to {}", path.display()),
+                        Err(e) => println!("failed to save screenshot: {e}"),
+                    }
+                }
+
+                renderer.update(dt, world.resource::<Bindings>(), world.resource::<ChunkWorld>());
+                renderer.target = world.resource::<ChunkWorld>().raycast(renderer.camera.pos, renderer.camera.front, REACH);
+
+                let stage = std::time::Instant::now();
                 presenter.present(vk.clone());
+                stages.present = stage.elapsed();
+                stages.record = renderer.record_time;
+
+                let scene = stats::scene_stats(&renderer, &mut world);
+                let mut frame_stats = world.resource_mut::<FrameStats>();
+                frame_stats.stages = stages;
+                frame_stats.scene = scene;
+                frame_stats.push_frame(now.elapsed());
 
 
                 dt = now.elapsed().as_secs_f32();
@@ -146,64 +379,132 @@ async fn main() {
     });
 }
 
-pub fn get_pipeline(
-    vk: Arc<Vk>,
-    vs: Arc<ShaderModule>,
-    fs: Arc<ShaderModule>,
-
-    render_pass: Arc<RenderPass>,
-    viewport: Viewport,
-) -> Arc<GraphicsPipeline> {
-    let vs = vs.entry_point("main").unwrap();
-    let fs = fs.entry_point("main").unwrap();
-
-    let vertex_input_state = [PosVertex::per_vertex(), InstanceData::per_instance()]
-        .definition(&vs.info().input_interface)
-        .unwrap();
-
-    let stages = [
-        PipelineShaderStageCreateInfo::new(vs),
-        PipelineShaderStageCreateInfo::new(fs),
-    ];
-
-    let layout = PipelineLayout::new(
-        vk.device.clone(),
-        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-            .into_pipeline_layout_create_info(vk.device.clone())
-            .unwrap(),
-    )
-    .unwrap();
-
-    let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
-
-    GraphicsPipeline::new(
-        vk.device.clone(),
-        None,
-        GraphicsPipelineCreateInfo {
-            stages: stages.into_iter().collect(),
-            vertex_input_state: Some(vertex_input_state),
-            input_assembly_state: Some(InputAssemblyState {
-                primitive_restart_enable: false,
-                ..Default::default()
-            }),
-            viewport_state: Some(ViewportState {
-                viewports: [viewport].into_iter().collect(),
-                ..Default::default()
-            }),
-            rasterization_state: Some(RasterizationState {
-                cull_mode: vulkano::pipeline::graphics::rasterization::CullMode::Back,
-                front_face: vulkano::pipeline::graphics::rasterization::FrontFace::Clockwise,
-                ..Default::default()
-            }),
-            multisample_state: Some(MultisampleState::default()),
-            color_blend_state: Some(ColorBlendState::with_attachment_states(
-                subpass.num_color_attachments(),
-                ColorBlendAttachmentState::default(),
-            )),
-            subpass: Some(subpass.into()),
-            depth_stencil_state: Some(DepthStencilState::simple_depth_test()),
-            ..GraphicsPipelineCreateInfo::layout(layout)
-        },
-    )
-    .unwrap()
-}
\ No newline at end of file
+/// `in_order_builder` for headless runs, see `ChunkBuilder::begin_loop`
+fn build_app(gpu: Arc<Gpu>, in_order_builder: bool) -> App {
+    let mut app = App::new();
+
+    app
+        .add_systems(Startup, (mesh_spawner::startup, lights::startup, fog::startup, debug::startup, prototypes::startup))
+        .add_systems(Update, (script_commands::apply, mesh_spawner::update, prototypes::sync).chain())
+        .insert_resource(GpuContext(gpu.clone()))
+        .init_resource::<FrameStats>()
+        .init_resource::<Brushes>()
+        .init_resource::<Clipboard>()
+        .init_resource::<ScriptQueue>()
+        .insert_resource(load_bindings());
+
+    insert_chunkworld_resource(app.world_mut().commands(), gpu.allocators.clone(), in_order_builder);
+
+    app
+}
+
+/// A small cross in the middle of the screen, the voxel it's on is outlined
+fn draw_crosshair(ui: &imgui::Ui, extent: [u32; 2]) {
+    let center = [extent[0] as f32 * 0.5, extent[1] as f32 * 0.5];
+    let size = 8.0;
+    let color = [1.0, 1.0, 1.0, 0.8];
+
+    let draw_list = ui.get_foreground_draw_list();
+    draw_list
+        .add_line([center[0] - size, center[1]], [center[0] + size, center[1]], color)
+        .thickness(2.0)
+        .build();
+    draw_list
+        .add_line([center[0], center[1] - size], [center[0], center[1] + size], color)
+        .thickness(2.0)
+        .build();
+}
+
+/// Every action with its default keys, replaced by the ones in the bindings file if there is one
+fn load_bindings() -> Bindings {
+    let mut bindings = Bindings::new();
+
+    let path = std::path::Path::new(input::BINDINGS_FILE);
+    if path.exists() {
+        if let Err(e) = bindings.load(path) {
+            println!("failed to load key bindings: {e}");
+        }
+    }
+
+    renderer::register_actions(&mut bindings);
+    LuaIntegration::register_actions(&mut bindings);
+    editing::register_actions(&mut bindings);
+    bindings.register("screenshot", &[VirtualKeyCode::F12]);
+    bindings.register("toggle_fullscreen", &[VirtualKeyCode::F11]);
+
+    bindings
+}
+
+/// Runs and renders the world for a number of frames without showing a window,
+/// then saves what the camera sees. Handy for CI and for comparing renders
+async fn run_headless(args: HeadlessArgs) {
+    let gpu = Gpu::headless();
+    let mut app = build_app(gpu.clone(), true);
+
+    let [width, height] = args.extent;
+    let window_state = WindowState::new(width, height);
+    app.world_mut().insert_resource(window_state);
+
+    let mut renderer = Renderer::new();
+    renderer.camera.proj = window_state.projection();
+
+    let offscreen = Offscreen::new(gpu.clone(), Format::R8G8B8A8_UNORM, args.extent);
+    let pipelines = Pipelines::new(gpu.clone(), &Shaders::load(gpu.device.clone()), offscreen.rp.clone());
+
+    /* fixed timestep so runs are reproducible */
+    let dt = 1.0 / 60.0;
+    let mut frames = args.frames;
+
+    if let Some(path) = &args.camera_path {
+        let camera_path = CameraPath::load(path).expect("failed to load the camera path");
+        frames = args.frames_for(camera_path.duration(), dt);
+        renderer.path.play(camera_path);
+    }
+
+    let mut render_ms = 0.0;
+
+    for _ in 0..frames {
+        /* what the builder was asked for last step is in before this one, however slow it is */
+        app.world_mut().resource_mut::<ChunkWorld>().wait_for_builder(gpu.allocators.clone()).await;
+
+        let now = std::time::Instant::now();
+        let mut stages = StageTimings::default();
+
+        app.update();
+        stages.app_update = now.elapsed();
+
+        let stage = std::time::Instant::now();
+        app.world_mut().resource_mut::<ChunkWorld>().update(gpu.allocators.clone(), &renderer.camera, &window_state);
+        stages.chunk_update = stage.elapsed();
+
+        renderer.update(dt, app.world().resource::<Bindings>(), app.world().resource::<ChunkWorld>());
+
+        let stage = std::time::Instant::now();
+        offscreen.draw(gpu.clone(), &renderer, &pipelines, app.world_mut());
+        stages.render = stage.elapsed();
+        render_ms += stages.render.as_secs_f64() * 1000.0;
+
+        let scene = stats::scene_stats(&renderer, app.world_mut());
+        let mut frame_stats = app.world_mut().resource_mut::<FrameStats>();
+        frame_stats.stages = stages;
+        frame_stats.scene = scene;
+        frame_stats.push_frame(now.elapsed());
+    }
+
+    offscreen
+        .screenshot(gpu.clone(), &renderer, &pipelines, app.world_mut(), &args.out)
+        .expect("failed to save the headless render");
+
+    println!("saved headless render to {}", args.out.display());
+
+    let frame_stats = app.world().resource::<FrameStats>();
+    let frame_count = frame_stats.frame_count.max(1) as f64;
+    println!(
+        "{} frames, {:.3} ms average ({:.3} ms of it rendering), {:.3} ms worst", 
+        frame_stats.frame_count, 
+        frame_stats.total_ms / frame_count, 
+        render_ms / frame_count,
+        frame_stats.worst_ms,
+    );
+    println!("{:?}", frame_stats.scene);
+}
@@ -1,56 +1,19 @@
 use std::{sync::{Arc, Mutex}, vec};
 
-use bevy_ecs::{component::Component, entity::Entity, query::With, system::{Commands, Query, Res, ResMut, Resource}, world::World};
-use chaos_vk::graphics::{mesh::mesh::Mesh, vertex::PosVertex};
-use glam::Vec3;
-use mlua::FromLua;
-use rlua::{UserData, UserDataMethods};
-
-use crate::geometry::sphere;
-
-#[derive(Component)]
-pub struct MeshComponent {
-    pub vertices: Vec<PosVertex>,
-    pub indices: Vec<u32>,
-    pub position: Vec3,
-}
-
-#[derive(Clone, Copy, FromLua, Debug)]
-pub enum SpawnCommand {
-    Sphere(Vec3, f32), /* pos and radius */
-    Clear,
-}
-
-impl SpawnCommand {
-    pub fn encode(&self) -> u128 {
-        match self {
-            SpawnCommand::Sphere(pos, rad) => {
-                let x = pos.x.to_bits() as u128;
-                let y = pos.y.to_bits() as u128;
-                let z = pos.z.to_bits() as u128;
-                let r = rad.to_bits() as u128;
-
-                (x << 96) | (y << 64) | (z << 32) | r
-            }
-            SpawnCommand::Clear => 69420,
-        }
-    }
+use bevy_ecs::{entity::Entity, query::With, system::{Commands, Query, Res, ResMut, Resource}, world::World};
+use glam::{vec3, Vec3};
 
-    pub fn decode(packed: u128) -> Self {
-        if packed == 69420 {
-            return SpawnCommand::Clear;
-        }
+use crate::{geometry::sphere, prototypes::{Instance, Prototype}};
 
-        let x = f32::from_bits((packed >> 96) as u32);
-        let y = f32::from_bits((packed >> 64) as u32);
-        let z = f32::from_bits((packed >> 32) as u32);
-        let r = f32::from_bits(packed as u32);
+pub const SPHERE_COLOR: Vec3 = vec3(0.9, 0.6, 0.3);
 
-        SpawnCommand::Sphere(Vec3::new(x, y, z), r)
-    }
+#[derive(Clone, Copy, Debug)]
+pub enum SpawnCommand {
+    Sphere { pos: Vec3, radius: f32, color: Vec3 },
+    Clear,
 }
 
-#[derive(Resource, FromLua, Clone, Debug)]
+#[derive(Resource, Clone, Debug)]
 pub struct SpawnCommandBuffer {
     pub commands: Vec<SpawnCommand>,
 }
@@ -64,32 +27,63 @@ pub fn startup(mut commands: Commands) {
 pub fn update(
     mut spawn_commands: ResMut<SpawnCommandBuffer>,
     mut commands: Commands,
-    meshes: Query<Entity, With<MeshComponent>>,
+    instances: Query<Entity, With<Instance>>,
 ) {
-    let spawn_commands_read = &spawn_commands.commands;
-    
-    for command in spawn_commands_read {
-        match command {
-            SpawnCommand::Sphere(pos, radius) => {
-                let sphere = sphere(16, *radius, *pos);
-
-                commands.spawn(MeshComponent {
-                    vertices: sphere.vertices,
-                    indices: sphere.indices,
-                    position: *pos,
+    let queued: Vec<SpawnCommand> = spawn_commands.commands.drain(..).collect();
+
+    /*
+    `Clear` only sees the entities of earlier frames, the ones spawned here
+    only exist once `commands` is applied. So everything before the last
+    `Clear` of the frame is skipped instead of spawned and despawned
+    */
+    let last_clear = queued.iter().rposition(|command| matches!(command, SpawnCommand::Clear));
+    if last_clear.is_some() {
+        for instance in instances.iter() {
+            commands.entity(instance).despawn();
+        }
+    }
+
+    for command in &queued[last_clear.map_or(0, |i| i + 1)..] {
+        match *command {
+            SpawnCommand::Sphere { pos, radius, color } => {
+                commands.spawn(Instance {
+                    prototype: Prototype::Sphere,
+                    pos,
+                    scale: radius,
+                    color,
                 });
             },
 
-            SpawnCommand::Clear => {
-                for mesh in meshes.iter() {
-                    commands.entity(mesh).remove::<MeshComponent>();
-                }
-            }
+            SpawnCommand::Clear => unreachable!("after the last clear"),
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::{system::RunSystemOnce, world::World};
+    use glam::Vec3;
+
+    use crate::prototypes::Instance;
+
+    use super::{update, SpawnCommand, SpawnCommandBuffer, SPHERE_COLOR};
+
+    fn sphere() -> SpawnCommand {
+        SpawnCommand::Sphere { pos: Vec3::ZERO, radius: 1.0, color: SPHERE_COLOR }
+    }
 
-    if !spawn_commands.commands.is_empty() {
-        spawn_commands.commands.pop();
+    fn frame(world: &mut World, commands: Vec<SpawnCommand>) -> usize {
+        world.insert_resource(SpawnCommandBuffer { commands });
+        world.run_system_once(update);
+        world.query::<&Instance>().iter(world).count()
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn clear_removes_spheres_spawned_in_the_same_frame() {
+        let mut world = World::new();
+
+        assert_eq!(frame(&mut world, vec![sphere()]), 1);
+        assert_eq!(frame(&mut world, vec![sphere(), SpawnCommand::Clear]), 0);
+        assert_eq!(frame(&mut world, vec![sphere(), SpawnCommand::Clear, sphere(), sphere()]), 2);
+    }
+}
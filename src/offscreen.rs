@@ -0,0 +1,311 @@
+use std::{fs::{self, File}, io::BufWriter, path::{Path, PathBuf}, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+
+use bevy_ecs::world::World;
+use chaos_vk::graphics::{buffer::VkIterBuffer, command::VkBuilder, image::VkImage};
+use vulkano::{command_buffer::{CopyImageToBufferInfo, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents}, format::Format, image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage}, memory::allocator::AllocationCreateInfo, render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass}};
+
+use crate::{pipelines::Pipelines, renderer::{record_scene, scene_descriptors, Renderer}, vk_mod::Gpu};
+
+/// A render target that isn't the swapchain, used for screenshots and headless rendering.
+///
+/// Its render pass is compatible with the swapchain's one as long as `format`
+/// is the swapchain image format, so the same `Pipelines` work for both
+pub struct Offscreen {
+    pub rp: Arc<RenderPass>,
+    pub image: Arc<Image>,
+    pub framebuffer: Arc<Framebuffer>,
+    pub format: Format,
+    pub extent: [u32; 2],
+}
+
+impl Offscreen {
+    pub fn new(gpu: Arc<Gpu>, format: Format, extent: [u32; 2]) -> Self {
+        let rp = vulkano::single_pass_renderpass!(gpu.device.clone(),
+            attachments: {
+                color_attachment: {
+                    format: format,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+
+                depth_attachment: {
+                    format: Format::D16_UNORM,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                }
+            },
+            pass: {
+                color: [color_attachment],
+                depth_stencil: {depth_attachment},
+            },
+        )
+        .unwrap();
+
+        let image = Image::new(
+            gpu.allocators.memory.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [extent[0], extent[1], 1],
+                usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+
+        let depth = VkImage::depth(gpu.allocators.clone(), Format::D16_UNORM, image.extent()).content;
+
+        let framebuffer = Framebuffer::new(
+            rp.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![
+                    ImageView::new_default(image.clone()).unwrap(),
+                    ImageView::new_default(depth).unwrap(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        Self {
+            rp,
+            image,
+            framebuffer,
+            format,
+            extent,
+        }
+    }
+
+    /// Renders the scene (without imgui) and waits for the gpu to be done
+    pub fn draw(
+        &self,
+        gpu: Arc<Gpu>,
+        renderer: &Renderer,
+        pipelines: &Pipelines,
+        world: &mut World,
+    ) {
+        let builder = self.record(gpu.clone(), renderer, pipelines, world);
+
+        gpu.submit(builder.command_buffer())
+            .wait(None)
+            .unwrap();
+    }
+
+    /// Same as `draw`, then reads the image back as RGBA8 pixels
+    pub fn render(
+        &self,
+        gpu: Arc<Gpu>,
+        renderer: &Renderer,
+        pipelines: &Pipelines,
+        world: &mut World,
+    ) -> Vec<u8> {
+        let mut builder = self.record(gpu.clone(), renderer, pipelines, world);
+
+        let [width, height] = self.extent;
+        let buffer = VkIterBuffer::transfer_dst(
+            gpu.allocators.clone(),
+            (0..width * height * 4).map(|_| 0u8),
+        );
+
+        builder.0
+            .copy_image_to_buffer(
+                CopyImageToBufferInfo::image_buffer(self.image.clone(), buffer.content.clone())
+            )
+            .unwrap();
+
+        gpu.submit(builder.command_buffer())
+            .wait(None)
+            .unwrap();
+
+        let mut pixels = buffer.content.read().unwrap().to_vec();
+
+        /* swapchains usually are BGRA */
+        if matches!(self.format, Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        pixels
+    }
+
+    fn record(
+        &self,
+        gpu: Arc<Gpu>,
+        renderer: &Renderer,
+        pipelines: &Pipelines,
+        world: &mut World,
+    ) -> VkBuilder {
+        let descriptors = scene_descriptors(gpu.clone(), renderer, pipelines, world);
+
+        let mut builder = gpu.builder_once();
+
+        builder.0
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some(descriptors.clear_color.into()), Some(1.0.into())],
+                    ..RenderPassBeginInfo::framebuffer(self.framebuffer.clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        record_scene(gpu.clone(), &mut builder.0, self.extent, renderer, pipelines, &descriptors, world);
+
+        builder.0.end_render_pass(Default::default()).unwrap();
+
+        builder
+    }
+
+    pub fn screenshot(
+        &self,
+        gpu: Arc<Gpu>,
+        renderer: &Renderer,
+        pipelines: &Pipelines,
+        world: &mut World,
+        path: &Path,
+    ) -> Result<(), png::EncodingError> {
+        let pixels = self.render(gpu, renderer, pipelines, world);
+
+        save_png(path, self.extent, &pixels)
+    }
+}
+
+pub fn save_png(path: &Path, extent: [u32; 2], rgba: &[u8]) -> Result<(), png::EncodingError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let file = BufWriter::new(File::create(path)?);
+
+    let mut encoder = png::Encoder::new(file, extent[0], extent[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+
+    Ok(())
+}
+
+/// screenshots/<unix time in ms>.png
+pub fn screenshot_path() -> PathBuf {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    PathBuf::from("screenshots").join(format!("{time}.png"))
+}
+
+/// `--headless [--frames n] [--size width height] [--out path.png] [--path camera_path.txt]`
+pub struct HeadlessArgs {
+    pub frames: usize,
+    pub extent: [u32; 2],
+    pub out: PathBuf,
+    /// camera path to play back, runs for as long as the path unless `--frames` is given
+    pub camera_path: Option<PathBuf>,
+    frames_given: bool,
+}
+
+impl HeadlessArgs {
+    /// `None` without `--headless`
+    pub fn from_args() -> Result<Option<Self>, String> {
+        let args: Vec<String> = std::env::args().collect();
+        if !args.iter().any(|arg| arg == "--headless") {
+            return Ok(None);
+        }
+
+        Self::parse(&args).map(Some)
+    }
+
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut headless = Self {
+            frames: 120,
+            extent: [1200, 900],
+            out: PathBuf::from("headless.png"),
+            camera_path: None,
+            frames_given: false,
+        };
+
+        /* the `n`th value after the option at `i` */
+        let value = |i: usize, n: usize, what: &str| args.get(i + n).ok_or(format!("{} expects {what}", args[i]));
+        let number = |i: usize, n: usize, what: &str| {
+            let value = value(i, n, what)?;
+            value.parse::<u32>().map_err(|_| format!("{} expects {what}, got {value}", args[i]))
+        };
+
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--frames" => {
+                    headless.frames = number(i, 1, "a number")? as usize;
+                    headless.frames_given = true;
+                    i += 1;
+                }
+                "--size" => {
+                    headless.extent = [number(i, 1, "a width")?, number(i, 2, "a height")?];
+                    i += 2;
+                }
+                "--out" => {
+                    headless.out = PathBuf::from(value(i, 1, "a path")?);
+                    i += 1;
+                }
+                "--path" => {
+                    headless.camera_path = Some(PathBuf::from(value(i, 1, "a path")?));
+                    i += 1;
+                }
+                _ => (),
+            }
+            i += 1;
+        }
+
+        Ok(headless)
+    }
+
+    /// Frames needed to go through a path of `duration` seconds at `dt`
+    pub fn frames_for(&self, duration: f32, dt: f32) -> usize {
+        if self.frames_given {
+            self.frames
+        } else {
+            (duration / dt).ceil() as usize + 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<HeadlessArgs, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        HeadlessArgs::parse(&args)
+    }
+
+    #[test]
+    fn reads_every_option() {
+        let args = parse("p2 --headless --frames 10 --size 64 32 --out a.png --path b.txt").unwrap();
+
+        assert_eq!(args.frames, 10);
+        assert_eq!(args.extent, [64, 32]);
+        assert_eq!(args.out, PathBuf::from("a.png"));
+        assert_eq!(args.camera_path, Some(PathBuf::from("b.txt")));
+    }
+
+    #[test]
+    fn missing_values_are_errors() {
+        for args in ["--frames", "--size 64", "--size", "--out", "--path"] {
+            let err = parse(&format!("p2 --headless {args}")).err();
+            assert!(err.is_some(), "{args}");
+        }
+
+        assert_eq!(parse("p2 --headless --size 64").err().unwrap(), "--size expects a height");
+        assert!(parse("p2 --headless --frames many").is_err());
+    }
+}
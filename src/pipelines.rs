@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use chaos_vk::graphics::vertex::{InstanceData, PosVertex};
+use vulkano::{device::Device, pipeline::{graphics::{color_blend::{ColorBlendAttachmentState, ColorBlendState}, depth_stencil::DepthStencilState, input_assembly::{InputAssemblyState, PrimitiveTopology}, multisample::MultisampleState, rasterization::{CullMode, FrontFace, PolygonMode, RasterizationState}, vertex_input::{Vertex, VertexDefinition}, viewport::ViewportState, GraphicsPipelineCreateInfo}, layout::PipelineDescriptorSetLayoutCreateInfo, DynamicState, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo}, render_pass::{RenderPass, Subpass}, shader::ShaderModule};
+
+use crate::{vk_mod::Gpu, shaders::{fs, instanced_fs, instanced_vs, line_fs, line_vs, mesh_fs, mesh_vs, vs}, vertex::{ChunkVertex, LineVertex, PrototypeInstance}};
+
+/// Every shader module the renderer uses
+#[derive(Clone)]
+pub struct Shaders {
+    pub vs: Arc<ShaderModule>,
+    pub fs: Arc<ShaderModule>,
+    pub mesh_vs: Arc<ShaderModule>,
+    pub mesh_fs: Arc<ShaderModule>,
+    pub line_vs: Arc<ShaderModule>,
+    pub line_fs: Arc<ShaderModule>,
+    pub instanced_vs: Arc<ShaderModule>,
+    pub instanced_fs: Arc<ShaderModule>,
+}
+
+impl Shaders {
+    pub fn load(device: Arc<Device>) -> Self {
+        Self {
+            vs: vs::load(device.clone()).unwrap(),
+            fs: fs::load(device.clone()).unwrap(),
+            mesh_vs: mesh_vs::load(device.clone()).unwrap(),
+            mesh_fs: mesh_fs::load(device.clone()).unwrap(),
+            line_vs: line_vs::load(device.clone()).unwrap(),
+            line_fs: line_fs::load(device.clone()).unwrap(),
+            instanced_vs: instanced_vs::load(device.clone()).unwrap(),
+            instanced_fs: instanced_fs::load(device.clone()).unwrap(),
+        }
+    }
+}
+
+/// All the pipelines drawing the scene, they have to be rebuilt together
+/// whenever the render pass changes. The viewport and scissor are dynamic
+/// state, see `window::viewport`
+#[derive(Clone)]
+pub struct Pipelines {
+    pub chunk: Arc<GraphicsPipeline>,
+    /// `None` when the device can't rasterize lines (no `fill_mode_non_solid`)
+    pub chunk_wireframe: Option<Arc<GraphicsPipeline>>,
+    pub mesh: Arc<GraphicsPipeline>,
+    pub lines: Arc<GraphicsPipeline>,
+    /// prototype meshes, with `PrototypeInstance` as the instance input
+    pub instanced: Arc<GraphicsPipeline>,
+}
+
+impl Pipelines {
+    pub fn new(gpu: Arc<Gpu>, shaders: &Shaders, render_pass: Arc<RenderPass>) -> Self {
+        Self::try_new(gpu, shaders, render_pass).unwrap()
+    }
+
+    /// Fails if the shaders don't fit the vertex types or each other,
+    /// which can happen with shaders reloaded at runtime
+    pub fn try_new(gpu: Arc<Gpu>, shaders: &Shaders, render_pass: Arc<RenderPass>) -> Result<Self, String> {
+        let chunk_wireframe = if gpu.device.enabled_features().fill_mode_non_solid {
+            Some(build_pipeline::<ChunkVertex, InstanceData>(gpu.clone(), shaders.vs.clone(), shaders.fs.clone(), render_pass.clone(), PrimitiveTopology::TriangleList, PolygonMode::Line, CullMode::None)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            chunk: build_pipeline::<ChunkVertex, InstanceData>(gpu.clone(), shaders.vs.clone(), shaders.fs.clone(), render_pass.clone(), PrimitiveTopology::TriangleList, PolygonMode::Fill, CullMode::Back)?,
+            chunk_wireframe,
+            mesh: build_pipeline::<PosVertex, InstanceData>(gpu.clone(), shaders.mesh_vs.clone(), shaders.mesh_fs.clone(), render_pass.clone(), PrimitiveTopology::TriangleList, PolygonMode::Fill, CullMode::Back)?,
+            lines: build_pipeline::<LineVertex, InstanceData>(gpu.clone(), shaders.line_vs.clone(), shaders.line_fs.clone(), render_pass.clone(), PrimitiveTopology::LineList, PolygonMode::Fill, CullMode::None)?,
+            instanced: build_pipeline::<PosVertex, PrototypeInstance>(gpu.clone(), shaders.instanced_vs.clone(), shaders.instanced_fs.clone(), render_pass, PrimitiveTopology::TriangleList, PolygonMode::Fill, CullMode::None)?,
+        })
+    }
+}
+
+/// `V` is the per vertex input, `I` the per instance one
+fn build_pipeline<V: Vertex, I: Vertex>(
+    gpu: Arc<Gpu>,
+    vs: Arc<ShaderModule>,
+    fs: Arc<ShaderModule>,
+
+    render_pass: Arc<RenderPass>,
+
+    topology: PrimitiveTopology,
+    polygon_mode: PolygonMode,
+    cull_mode: CullMode,
+) -> Result<Arc<GraphicsPipeline>, String> {
+    let vs = vs.entry_point("main").ok_or("the vertex shader has no main")?;
+    let fs = fs.entry_point("main").ok_or("the fragment shader has no main")?;
+
+    let vertex_input_state = [V::per_vertex(), I::per_instance()]
+        .definition(&vs.info().input_interface)
+        .map_err(|e| e.to_string())?;
+
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+
+    let layout = PipelineLayout::new(
+        gpu.device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(gpu.device.clone())
+            .map_err(|e| format!("{:?}", e))?,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+    GraphicsPipeline::new(
+        gpu.device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState {
+                topology,
+                primitive_restart_enable: false,
+                ..Default::default()
+            }),
+            /* set with `set_viewport` and `set_scissor` when drawing, so resizing doesn't need new pipelines */
+            viewport_state: Some(ViewportState::default()),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor].into_iter().collect(),
+            rasterization_state: Some(RasterizationState {
+                polygon_mode,
+                cull_mode,
+                front_face: FrontFace::Clockwise,
+                ..Default::default()
+            }),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            subpass: Some(subpass.into()),
+            depth_stencil_state: Some(DepthStencilState::simple_depth_test()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .map_err(|e| e.to_string())
+}
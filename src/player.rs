@@ -0,0 +1,420 @@
+use glam::{ivec3, vec3, IVec3, Vec3};
+
+/*
+Walk mode: a capsule pushed around by gravity and kept out of solid voxels.
+
+Remember that the image is flipped vertically, "up" for the player is -Y in
+world space, so gravity pulls toward +Y.
+
+The collision code only needs to know which voxels are solid, so it can be
+run against a closure over a handful of hand made voxels as well as against
+`ChunkWorld::is_solid`.
+*/
+
+/// Direction the player considers up, in world space
+pub const UP: Vec3 = vec3(0.0, -1.0, 0.0);
+
+pub const GRAVITY: f32 = 25.0;
+pub const JUMP_SPEED: f32 = 8.0;
+pub const WALK_SPEED: f32 = 4.5;
+pub const SPRINT_SPEED: f32 = 8.0;
+pub const CROUCH_SPEED: f32 = 2.0;
+/// Highest ledge walked onto without jumping, a bit more than a voxel
+pub const STEP_HEIGHT: f32 = 1.05;
+
+pub const RADIUS: f32 = 0.3;
+pub const HEIGHT: f32 = 1.8;
+/// low enough to crawl through tunnels a voxel high
+pub const CROUCH_HEIGHT: f32 = 0.9;
+/// Distance from the eyes to the top of the capsule
+const EYE_OFFSET: f32 = 0.15;
+
+/// Penetration resolving passes per sub step
+const ITERATIONS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveMode {
+    Fly,
+    Walk,
+}
+
+/// Vertical capsule standing on `feet`, its lowest point
+#[derive(Clone, Copy, Debug)]
+pub struct Capsule {
+    pub radius: f32,
+    pub height: f32,
+}
+
+impl Capsule {
+    /// Centers of the bottom and top spheres
+    pub fn segment(&self, feet: Vec3) -> (Vec3, Vec3) {
+        (
+            feet + UP * self.radius,
+            feet + UP * (self.height - self.radius),
+        )
+    }
+
+    pub fn overlaps(&self, feet: Vec3, solid: &impl Fn(IVec3) -> bool) -> bool {
+        let (a, b) = self.segment(feet);
+
+        candidate_voxels(a, b, self.radius).any(|voxel| {
+            solid(voxel) && {
+                let (on_segment, on_box) = closest_points(a, b, voxel);
+                on_segment.distance_squared(on_box) < self.radius * self.radius
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MoveResult {
+    pub feet: Vec3,
+    /// touched something below the capsule
+    pub grounded: bool,
+    /// touched something above it
+    pub hit_ceiling: bool,
+}
+
+/// Moves a capsule by `motion`, sliding along the voxels it runs into
+pub fn move_capsule(solid: &impl Fn(IVec3) -> bool, capsule: Capsule, feet: Vec3, motion: Vec3) -> MoveResult {
+    let mut result = MoveResult {
+        feet,
+        ..Default::default()
+    };
+
+    /* small steps so fast motion can't tunnel through a voxel */
+    let steps = (motion.length() / (capsule.radius * 0.5)).ceil().max(1.0) as usize;
+    let step = motion / steps as f32;
+
+    for _ in 0..steps {
+        result.feet += step;
+        resolve(solid, capsule, &mut result);
+    }
+
+    result
+}
+
+/// Pushes the capsule out of every solid voxel it overlaps
+fn resolve(solid: &impl Fn(IVec3) -> bool, capsule: Capsule, result: &mut MoveResult) {
+    for _ in 0..ITERATIONS {
+        let (a, b) = capsule.segment(result.feet);
+        let mut pushed = false;
+
+        for voxel in candidate_voxels(a, b, capsule.radius) {
+            if !solid(voxel) {
+                continue;
+            }
+
+            let (a, b) = capsule.segment(result.feet);
+            let (on_segment, on_box) = closest_points(a, b, voxel);
+            let d = on_segment - on_box;
+            let dist = d.length();
+
+            if dist >= capsule.radius {
+                continue;
+            }
+
+            let (normal, depth) = if dist > 1e-5 {
+                (d / dist, capsule.radius - dist)
+            } else {
+                /* the segment itself is inside the voxel, get out the shortest way */
+                inside_push(on_segment, voxel, capsule.radius)
+            };
+
+            result.feet += normal * depth;
+            pushed = true;
+
+            let up = normal.dot(UP);
+            if up > 0.7 {
+                result.grounded = true;
+            } else if up < -0.7 {
+                result.hit_ceiling = true;
+            }
+        }
+
+        if !pushed {
+            break;
+        }
+    }
+}
+
+/// Voxels close enough to the segment to touch the capsule
+fn candidate_voxels(a: Vec3, b: Vec3, radius: f32) -> impl Iterator<Item = IVec3> {
+    let min = (a.min(b) - Vec3::splat(radius)).floor().as_ivec3();
+    let max = (a.max(b) + Vec3::splat(radius)).floor().as_ivec3();
+
+    (min.x..=max.x).flat_map(move |x| {
+        (min.y..=max.y).flat_map(move |y| {
+            (min.z..=max.z).map(move |z| ivec3(x, y, z))
+        })
+    })
+}
+
+/// Closest points between a vertical segment and the unit box of a voxel.
+/// Exact, since the segment is parallel to one of the box's axes
+fn closest_points(a: Vec3, b: Vec3, voxel: IVec3) -> (Vec3, Vec3) {
+    let min = voxel.as_vec3();
+    let max = min + Vec3::ONE;
+
+    let (lo, hi) = (a.y.min(b.y), a.y.max(b.y));
+    let y = if hi < min.y {
+        hi
+    } else if lo > max.y {
+        lo
+    } else {
+        /* the intervals overlap, any y in between is as close */
+        (lo.max(min.y) + hi.min(max.y)) * 0.5
+    };
+
+    let on_segment = vec3(a.x, y, a.z);
+    (on_segment, on_segment.clamp(min, max))
+}
+
+/// Normal and depth to push a point out of a voxel along the shallowest axis
+fn inside_push(point: Vec3, voxel: IVec3, radius: f32) -> (Vec3, f32) {
+    let min = voxel.as_vec3();
+    let max = min + Vec3::ONE;
+
+    let exits = [
+        (Vec3::NEG_X, point.x - min.x),
+        (Vec3::X, max.x - point.x),
+        (Vec3::NEG_Y, point.y - min.y),
+        (Vec3::Y, max.y - point.y),
+        (Vec3::NEG_Z, point.z - min.z),
+        (Vec3::Z, max.z - point.z),
+    ];
+
+    let (normal, depth) = exits
+        .into_iter()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap();
+
+    (normal, depth + radius)
+}
+
+/// What the player wants to do this frame, in world space
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WalkInput {
+    /// horizontal, not normalized
+    pub wish_dir: Vec3,
+    pub jump: bool,
+    pub crouch: bool,
+    pub sprint: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Player {
+    pub feet: Vec3,
+    pub velocity: Vec3,
+    pub grounded: bool,
+    pub crouching: bool,
+}
+
+impl Player {
+    /// Puts the eyes at `eye`
+    pub fn new(eye: Vec3) -> Self {
+        let mut player = Self {
+            feet: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            grounded: false,
+            crouching: false,
+        };
+        player.feet = eye - UP * player.eye_height();
+
+        player
+    }
+
+    pub fn capsule(&self) -> Capsule {
+        Capsule {
+            radius: RADIUS,
+            height: if self.crouching { CROUCH_HEIGHT } else { HEIGHT },
+        }
+    }
+
+    pub fn eye_height(&self) -> f32 {
+        self.capsule().height - EYE_OFFSET
+    }
+
+    pub fn eye(&self) -> Vec3 {
+        self.feet + UP * self.eye_height()
+    }
+
+    pub fn update(&mut self, dt: f32, input: WalkInput, solid: &impl Fn(IVec3) -> bool) {
+        /* only stand back up if there's room for it */
+        if input.crouch {
+            self.crouching = true;
+        } else if self.crouching {
+            let standing = Capsule { radius: RADIUS, height: HEIGHT };
+            if !standing.overlaps(self.feet, solid) {
+                self.crouching = false;
+            }
+        }
+
+        let speed = if self.crouching {
+            CROUCH_SPEED
+        } else if input.sprint {
+            SPRINT_SPEED
+        } else {
+            WALK_SPEED
+        };
+
+        let horizontal = input.wish_dir.normalize_or_zero() * speed;
+        let vertical = self.velocity.dot(UP);
+
+        let mut vertical = vertical - GRAVITY * dt;
+        if input.jump && self.grounded && !self.crouching {
+            vertical = JUMP_SPEED;
+        }
+
+        self.velocity = horizontal + UP * vertical;
+
+        let capsule = self.capsule();
+        let motion = self.velocity * dt;
+        let mut result = move_capsule(solid, capsule, self.feet, motion);
+
+        /*
+        blocked by a ledge while on the ground: try climbing it, and keep
+        that if it gets us further along
+        */
+        if self.grounded && horizontal != Vec3::ZERO {
+            let wanted = horizontal * dt;
+            let progress = (result.feet - self.feet).dot(wanted);
+
+            if progress < wanted.length_squared() * 0.5 {
+                /* 
+                the rounded bottom slides off edges, so look for the top of the
+                ledge far enough ahead to get the center over it, even when
+                coming in at an angle
+                */
+                let probe = wanted.normalize() * capsule.radius * 2.0;
+
+                let raised = move_capsule(solid, capsule, self.feet, UP * STEP_HEIGHT);
+                let ahead = move_capsule(solid, capsule, raised.feet, probe);
+                let lowered = move_capsule(solid, capsule, ahead.feet, -UP * STEP_HEIGHT);
+                let rise = (lowered.feet - self.feet).dot(UP);
+
+                /* but only go up that high and as far as walking would this frame */
+                if lowered.grounded && rise > 0.0 {
+                    let up = move_capsule(solid, capsule, self.feet, UP * rise);
+                    let stepped = move_capsule(solid, capsule, up.feet, wanted);
+
+                    if (stepped.feet - self.feet).dot(wanted) > progress {
+                        result = MoveResult { grounded: true, ..stepped };
+                    }
+                }
+            }
+        }
+
+        self.feet = result.feet;
+        self.grounded = result.grounded;
+
+        if result.grounded && self.velocity.dot(UP) < 0.0 || result.hit_ceiling && self.velocity.dot(UP) > 0.0 {
+            self.velocity -= UP * self.velocity.dot(UP);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec3, IVec3, Vec3};
+
+    use super::{Player, WalkInput, HEIGHT, RADIUS, UP};
+
+    const DT: f32 = 1.0 / 60.0;
+
+    /* up is -Y, so the floor is everything from y = 0 down */
+    fn floor(pos: IVec3) -> bool {
+        pos.y >= 0
+    }
+
+    fn walk(player: &mut Player, input: WalkInput, seconds: f32, solid: &impl Fn(IVec3) -> bool) {
+        for _ in 0..(seconds / DT) as usize {
+            player.update(DT, input, solid);
+        }
+    }
+
+    fn forward(wish_dir: Vec3) -> WalkInput {
+        WalkInput { wish_dir, ..Default::default() }
+    }
+
+    /// Standing on the floor at `x, z`
+    fn standing(x: f32, z: f32, solid: &impl Fn(IVec3) -> bool) -> Player {
+        let mut player = Player::new(vec3(x, 0.0, z) + UP * HEIGHT);
+        walk(&mut player, WalkInput::default(), 0.5, solid);
+        player
+    }
+
+    #[test]
+    fn lands_on_the_floor() {
+        let mut player = Player::new(vec3(0.5, -5.0, 0.5));
+        walk(&mut player, WalkInput::default(), 2.0, &floor);
+
+        assert!(player.grounded);
+        assert!(player.feet.y.abs() < 0.01, "{}", player.feet);
+        assert!(player.velocity.y.abs() < 1.0);
+    }
+
+    #[test]
+    fn slides_along_a_wall() {
+        let solid = |pos: IVec3| floor(pos) || pos.x >= 2;
+        let mut player = standing(0.5, 0.5, &solid);
+        walk(&mut player, forward(vec3(1.0, 0.0, 1.0)), 1.0, &solid);
+
+        assert!(player.feet.x <= 2.0 - RADIUS + 0.01, "{}", player.feet);
+        assert!(player.feet.z > 2.5, "{}", player.feet);
+        assert!(player.grounded);
+    }
+
+    #[test]
+    fn hits_the_ceiling_when_jumping() {
+        /* the ceiling is 3 up, lower than a jump would go */
+        let solid = |pos: IVec3| floor(pos) || pos.y <= -4;
+        let mut player = standing(0.5, 0.5, &solid);
+
+        let mut highest: f32 = 0.0;
+        player.update(DT, WalkInput { jump: true, ..Default::default() }, &solid);
+        for _ in 0..60 {
+            player.update(DT, WalkInput::default(), &solid);
+            highest = highest.max(player.feet.dot(UP));
+        }
+
+        assert!(highest <= 3.0 - HEIGHT + 0.01, "{highest}");
+        assert!(highest > 1.0, "{highest}");
+        assert!(player.grounded);
+    }
+
+    #[test]
+    fn steps_up_a_voxel() {
+        let solid = |pos: IVec3| floor(pos) || pos.x >= 2 && pos.y >= -1;
+        let mut player = standing(0.5, 0.5, &solid);
+
+        let mut last = player.feet;
+        for _ in 0..60 {
+            player.update(DT, forward(Vec3::X), &solid);
+            /* climbing doesn't jump ahead of walking speed */
+            assert!(player.feet.x - last.x <= super::WALK_SPEED * DT + 0.01, "{} -> {}", last, player.feet);
+            last = player.feet;
+        }
+
+        assert!((player.feet.y + 1.0).abs() < 0.01, "{}", player.feet);
+        assert!(player.feet.x > 3.0, "{}", player.feet);
+    }
+
+    #[test]
+    fn crouches_under_a_low_gap() {
+        /* a tunnel a voxel high from x = 2 to 6 */
+        let solid = |pos: IVec3| floor(pos) || (2..6).contains(&pos.x) && pos.y <= -2;
+
+        let mut standing_up = standing(0.5, 0.5, &solid);
+        walk(&mut standing_up, forward(Vec3::X), 1.0, &solid);
+        assert!(standing_up.feet.x < 2.0, "{}", standing_up.feet);
+
+        let mut crouching = standing(0.5, 0.5, &solid);
+        walk(&mut crouching, WalkInput { crouch: true, ..forward(Vec3::X) }, 1.5, &solid);
+        assert!(crouching.feet.x > 2.5, "{}", crouching.feet);
+
+        /* no room to stand up inside of it */
+        walk(&mut crouching, WalkInput::default(), 0.2, &solid);
+        assert!(crouching.crouching);
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use bevy_ecs::{component::Component, query::Changed, removal_detection::RemovedComponents, system::{Commands, Query, Res, ResMut, Resource}, world::World};
+use chaos_vk::graphics::{buffer::VkIterBuffer, vertex::PosVertex};
+use glam::{vec3, Vec3};
+use vulkano::buffer::Subbuffer;
+
+use crate::{geometry::{cube, sphere, GeometryData}, math::{rand_betw, rand_vec3}, vertex::PrototypeInstance, vk_mod::GpuContext};
+
+/*
+Shapes spawned over and over share a single gpu mesh, every entity only adds
+a `PrototypeInstance` to the instance buffer of its prototype. Everything with the
+same prototype gets drawn with one call.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Prototype {
+    Sphere,
+    Cube,
+}
+
+impl Prototype {
+    pub const ALL: [Prototype; 2] = [Prototype::Sphere, Prototype::Cube];
+
+    /// Unit sized geometry, scaled per instance
+    fn geometry(&self) -> GeometryData {
+        match self {
+            Prototype::Sphere => sphere(16, 1.0, Vec3::ZERO),
+            Prototype::Cube => cube(1.0, Vec3::ZERO),
+        }
+    }
+}
+
+/// An instance of a prototype mesh
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Instance {
+    pub prototype: Prototype,
+    pub pos: Vec3,
+    pub scale: f32,
+    pub color: Vec3,
+}
+
+impl Instance {
+    pub fn get_instance_data(&self) -> PrototypeInstance {
+        PrototypeInstance {
+            ofs: self.pos.to_array(),
+            scale: self.scale,
+            color: self.color.to_array(),
+        }
+    }
+}
+
+pub struct PrototypeMesh {
+    pub vbo: Subbuffer<[PosVertex]>,
+    pub ebo: Subbuffer<[u32]>,
+    /// `None` when there's nothing to draw
+    pub instances: Option<Subbuffer<[PrototypeInstance]>>,
+}
+
+#[derive(Resource)]
+pub struct Prototypes {
+    pub meshes: HashMap<Prototype, PrototypeMesh>,
+}
+
+pub fn startup(mut commands: Commands, gpu: Res<GpuContext>) {
+    let allocators = gpu.0.allocators.clone();
+
+    let meshes = Prototype::ALL
+        .iter()
+        .map(|prototype| {
+            let geometry = prototype.geometry();
+
+            (*prototype, PrototypeMesh {
+                vbo: VkIterBuffer::vertex(allocators.clone(), geometry.vertices).content,
+                ebo: VkIterBuffer::index(allocators.clone(), geometry.indices).content,
+                instances: None,
+            })
+        })
+        .collect();
+
+    commands.insert_resource(Prototypes { meshes });
+}
+
+/// Rebuilds the instance buffers whenever an instance is added, changed or removed
+pub fn sync(
+    gpu: Res<GpuContext>,
+    mut prototypes: ResMut<Prototypes>,
+    instances: Query<&Instance>,
+    changed: Query<(), Changed<Instance>>,
+    mut removed: RemovedComponents<Instance>,
+) {
+    let any_removed = removed.read().count() > 0;
+    if changed.is_empty() && !any_removed {
+        return;
+    }
+
+    let mut grouped: HashMap<Prototype, Vec<PrototypeInstance>> = HashMap::new();
+    for instance in instances.iter() {
+        grouped
+            .entry(instance.prototype)
+            .or_default()
+            .push(instance.get_instance_data());
+    }
+
+    for (prototype, mesh) in &mut prototypes.meshes {
+        mesh.instances = grouped
+            .remove(prototype)
+            .map(|data| VkIterBuffer::vertex(gpu.0.allocators.clone(), data).content);
+    }
+}
+
+/// Scatters `count` cubes of random colors around `center`, to stress test instancing
+pub fn spawn_markers(world: &mut World, center: Vec3, count: usize) {
+    let spread = 150.0;
+
+    world.spawn_batch((0..count).map(move |_| {
+        let ofs = vec3(
+            rand_betw(-spread, spread),
+            rand_betw(-spread, spread),
+            rand_betw(-spread, spread),
+        );
+
+        Instance {
+            prototype: Prototype::Cube,
+            pos: center + ofs,
+            scale: 0.5,
+            color: rand_vec3(),
+        }
+    }));
+}
@@ -0,0 +1,146 @@
+use glam::{IVec3, Vec3};
+
+use crate::world::{Chunk, ChunkKey};
+
+/// How far away voxels can be picked with the crosshair
+pub const REACH: f32 = 64.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    /// the voxel that was hit, in world space
+    pub voxel: IVec3,
+    pub key: ChunkKey,
+    /// index of the voxel inside of its chunk
+    pub index: usize,
+    /// face the ray went in through, zero if it started inside the voxel
+    pub normal: IVec3,
+    pub distance: f32,
+    /// where the ray hit the face
+    pub point: Vec3,
+}
+
+/*
+Amanatides & Woo's voxel traversal: the ray steps from one voxel to the next
+through whichever face it crosses first, so every voxel it goes through is
+visited exactly once, in order, without missing corners.
+
+Works on anything that can tell whether a voxel is solid, which is how it can
+be checked against a few hand placed voxels on both sides of zero.
+*/
+pub fn raycast(solid: &impl Fn(IVec3) -> bool, origin: Vec3, dir: Vec3, max_distance: f32) -> Option<RayHit> {
+    let dir = dir.normalize_or_zero();
+    if dir == Vec3::ZERO {
+        return None;
+    }
+
+    let mut voxel = origin.floor().as_ivec3();
+    let step = dir.signum().as_ivec3();
+
+    /* distance along the ray between two crossings on each axis */
+    let t_delta = dir.recip().abs();
+
+    /* distance to the first crossing on each axis */
+    let mut t_max = Vec3::ZERO;
+    for axis in 0..3 {
+        t_max[axis] = if dir[axis] > 0.0 {
+            (voxel[axis] as f32 + 1.0 - origin[axis]) / dir[axis]
+        } else if dir[axis] < 0.0 {
+            (origin[axis] - voxel[axis] as f32) / -dir[axis]
+        } else {
+            f32::INFINITY
+        };
+    }
+
+    let mut normal = IVec3::ZERO;
+    let mut distance = 0.0;
+
+    loop {
+        if solid(voxel) {
+            let (key, index) = Chunk::locate_voxel(voxel);
+
+            return Some(RayHit {
+                voxel,
+                key,
+                index,
+                normal,
+                distance,
+                point: origin + dir * distance,
+            });
+        }
+
+        let axis = if t_max.x < t_max.y {
+            if t_max.x < t_max.z { 0 } else { 2 }
+        } else {
+            if t_max.y < t_max.z { 1 } else { 2 }
+        };
+
+        distance = t_max[axis];
+        if distance > max_distance {
+            return None;
+        }
+
+        voxel[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{ivec3, vec3, IVec3};
+
+    use super::raycast;
+    use crate::world::{Chunk, CHUNK_SIZE};
+
+    #[test]
+    fn hits_a_voxel_below_zero_through_its_near_face() {
+        let solid = |pos: IVec3| pos == ivec3(-3, 0, 0);
+        let hit = raycast(&solid, vec3(0.5, 0.5, 0.5), vec3(-1.0, 0.0, 0.0), 10.0).unwrap();
+
+        assert_eq!(hit.voxel, ivec3(-3, 0, 0));
+        assert_eq!(hit.normal, IVec3::X);
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+        assert!((hit.point.x + 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn hits_a_voxel_above_zero_from_below_zero() {
+        let solid = |pos: IVec3| pos == ivec3(0, 2, 0);
+        let hit = raycast(&solid, vec3(0.5, -1.5, 0.5), vec3(0.0, 1.0, 0.0), 10.0).unwrap();
+
+        assert_eq!(hit.voxel, ivec3(0, 2, 0));
+        assert_eq!(hit.normal, IVec3::NEG_Y);
+        assert!((hit.distance - 3.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn crosses_chunk_borders() {
+        let size = CHUNK_SIZE as i32;
+        let wall = ivec3(-size - 1, 5, 7);
+        let solid = |pos: IVec3| pos == wall;
+        let hit = raycast(&solid, vec3(-size as f32 + 1.5, 5.5, 7.5), vec3(-1.0, 0.0, 0.0), 10.0).unwrap();
+
+        assert_eq!(hit.voxel, wall);
+        assert_eq!(hit.normal, IVec3::X);
+        assert_eq!(hit.key, (-2, 0, 0));
+        assert_eq!((hit.key, hit.index), Chunk::locate_voxel(wall));
+    }
+
+    #[test]
+    fn starting_inside_of_a_voxel_has_no_normal() {
+        let solid = |pos: IVec3| pos == ivec3(-1, -1, -1);
+        let hit = raycast(&solid, vec3(-0.5, -0.5, -0.5), vec3(1.0, 1.0, 1.0), 10.0).unwrap();
+
+        assert_eq!(hit.voxel, ivec3(-1, -1, -1));
+        assert_eq!(hit.normal, IVec3::ZERO);
+        assert_eq!(hit.distance, 0.0);
+    }
+
+    #[test]
+    fn misses_beyond_max_distance() {
+        let solid = |pos: IVec3| pos == ivec3(-20, 0, 0);
+        assert!(raycast(&solid, vec3(0.5, 0.5, 0.5), vec3(-1.0, 0.0, 0.0), 10.0).is_none());
+    }
+}
@@ -1,12 +1,12 @@
-use std::{collections::HashMap, sync::{Arc, Mutex}, thread::sleep_ms};
+use std::{collections::{hash_map::DefaultHasher, HashMap}, hash::{Hash, Hasher}, path::Path, sync::{Arc, Mutex}, thread::sleep_ms, time::{Duration, Instant}};
 
 use bevy_ecs::{component::Component, system::{Commands, Resource}, world::{Mut, World}};
-use chaos_vk::{graphics::{buffer::{VkBuffer, VkIterBuffer}, camera::Camera, command::{CommandBufferType, VkBuilder}, mesh::mesh::Mesh, presenter::Presenter, utils::{descriptor_set, VkSecRenderpass}, vertex::PosVertex, vk::Vk}, imgui_renderer::ImGui};
-use glam::{Mat4, Vec3};
+use chaos_vk::{graphics::{buffer::{VkBuffer, VkIterBuffer}, camera::Camera, command::{BuilderType, CommandBufferType, VkBuilder}, mesh::mesh::Mesh, presenter::Presenter, utils::VkSecRenderpass, vertex::{InstanceData, PosVertex}, vk::Vk}, imgui_renderer::ImGui};
+use glam::{IVec3, Mat4, Vec3};
 use threadpool::ThreadPool;
-use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage}, command_buffer::{CommandBufferInheritanceInfo, CommandBufferInheritanceRenderPassInfo, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents}, descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}, pipeline::{GraphicsPipeline, Pipeline}, render_pass::{Framebuffer, RenderPass, Subpass}};
+use vulkano::{buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer}, command_buffer::{allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferInheritanceInfo, CommandBufferInheritanceRenderPassInfo, RenderPassBeginInfo, SecondaryCommandBufferAbstract, SubpassBeginInfo, SubpassContents}, descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}, pipeline::{GraphicsPipeline, Pipeline}, render_pass::{Framebuffer, RenderPass, Subpass}};
 
-use crate::{chunkmesh::ChunkMesh, math::SecondOrderDynamics, mesh_spawner::MeshComponent, shaders::vs, world::ChunkWorld};
+use crate::{camera_path::{CameraMove, CameraMoves, CameraPath, PathState, CAMERA_PATH_FILE}, chunkmesh::{ChunkMesh, QuadIndexBuffer}, culler::ChunkCuller, debug::{self, DebugView}, editing, fog::Fog, input::Bindings, lights::Lights, math::SecondOrderDynamics, pipelines::Pipelines, prototypes::Prototypes, raycast::RayHit, schematic::Clipboard, shaders::{fs, vs}, player::{MoveMode, Player, WalkInput}, vk_mod::Gpu, window::{self, WindowState}, world::{Chunk, ChunkWorld}};
 
 #[derive(Resource)]
 pub struct Renderer {
@@ -15,126 +15,289 @@ pub struct Renderer {
 
     pub meshes: Vec<Mesh>,
     pub pool: ThreadPool,
-    pub keymap: [bool; 7],
+
+    pub move_mode: MoveMode,
+    /// only simulated in walk mode
+    pub player: Player,
+    /// camera path being recorded or played back
+    pub path: PathState,
+    /// queued by scripts, see `lua_camera`
+    pub moves: CameraMoves,
+    /// voxel under the crosshair
+    pub target: Option<RayHit>,
+
+    /// record every chunk once into its own secondary command buffer and
+    /// reuse it, instead of recording all the draws again every frame
+    pub secondary_chunks: bool,
+    /// cpu time `get_cmd_bufs` took on the last frame
+    pub record_time: Duration,
+
+    /// one per swapchain image, only used with `secondary_chunks`
+    frame_uniforms: Vec<FrameUniforms>,
+    uniforms_pipeline: Option<Arc<GraphicsPipeline>>,
+    /// bumped whenever `frame_uniforms` get recreated
+    uniforms_generation: u64,
 }
 
 impl Renderer {
     pub fn new() -> Self {
         let mut camera = Camera::new();
         camera.speed = 8.0;
-        camera.proj = Mat4::perspective_rh(80.0f32.to_radians(), 12.0/9.0, 0.1, 1000.0);
+        camera.proj = WindowState::new(1200, 900).projection();
         Self {
             camera,
             cam_sod: SecondOrderDynamics::new(2.75, 0.75, 0.0, camera.pos),
             meshes: vec![],
             pool: ThreadPool::new(num_cpus::get()),
-            keymap: [false; 7],
+
+            move_mode: MoveMode::Fly,
+            player: Player::new(camera.pos),
+            path: PathState::Idle,
+            moves: CameraMoves::default(),
+            target: None,
+
+            secondary_chunks: true,
+            record_time: Duration::ZERO,
+
+            frame_uniforms: vec![],
+            uniforms_pipeline: None,
+            uniforms_generation: 0,
         }
     }
 
-    pub fn update(&mut self, dt: f32) {
+    /// (Re)creates the per image uniforms when the pipelines or the amount
+    /// of swapchain images changed
+    fn prepare_frame_uniforms(&mut self, gpu: Arc<Gpu>, pipelines: &Pipelines, images: usize, world: &World) {
+        let same_pipeline = self.uniforms_pipeline
+            .as_ref()
+            .map_or(false, |pipeline| Arc::ptr_eq(pipeline, &pipelines.chunk));
+
+        if same_pipeline && self.frame_uniforms.len() == images {
+            return;
+        }
+
+        self.frame_uniforms = (0..images)
+            .map(|_| FrameUniforms::new(gpu.clone(), pipelines.chunk.clone(), self, world))
+            .collect();
+        self.uniforms_pipeline = Some(pipelines.chunk.clone());
+        self.uniforms_generation += 1;
+    }
+
+    pub fn update(&mut self, dt: f32, bindings: &Bindings, chunkworld: &ChunkWorld) {
         self.camera.dt = dt;
-        set_goal_according_to_input(&mut self.camera, self.keymap);
-        let y = self.cam_sod.update(self.camera.dt, self.camera.goal);
+
+        /* playback takes over the camera, input is ignored */
+        let was_playing = self.path.is_playing();
+        if let Some((pos, front)) = self.path.advance(dt, &self.camera) {
+            self.look(pos, front);
+            return;
+        }
+        if was_playing {
+            self.sync_movement();
+        }
+
+        /* so do scripted moves, smoothed like flying */
+        let was_moving = self.moves.is_moving();
+        if let Some((goal, look_at)) = self.moves.advance(dt) {
+            self.camera.goal = -goal;
+            let pos = -self.cam_sod.update(dt, self.camera.goal);
+            let front = look_at.map_or(self.camera.front, |target| (target - pos).normalize_or(self.camera.front));
+            self.look(pos, front);
+            return;
+        }
+        if was_moving && self.move_mode == MoveMode::Walk {
+            self.sync_movement();
+        }
+
         self.camera.right = Vec3::Y.cross(-self.camera.front).normalize();
         self.camera.up = self.camera.front.cross(self.camera.right).normalize();
-        self.camera.update(-y);
+
+        match self.move_mode {
+            MoveMode::Fly => {
+                set_goal_according_to_input(&mut self.camera, bindings);
+                let y = self.cam_sod.update(self.camera.dt, self.camera.goal);
+                self.camera.update(-y);
+            }
+            MoveMode::Walk => {
+                /* wait for the ground to be loaded before falling on it */
+                let key = Chunk::get_ijk_chunkspace(self.player.feet);
+                if chunkworld.chunk(&key).is_some() {
+                    let input = walk_input(&self.camera, bindings);
+                    self.player.update(dt, input, &|pos| chunkworld.is_solid(pos));
+                }
+
+                self.camera.update(self.player.eye());
+            }
+        }
     }
-}
 
-pub fn get_keymap(
-    event: &winit::event::WindowEvent,
-    keymap: &mut [bool; 7],
-) -> [bool;7] {
-    match event {
-        winit::event::WindowEvent::KeyboardInput { input, .. } => {
-            let action = match input.state {
-                winit::event::ElementState::Pressed => true,
-                winit::event::ElementState::Released => false,
-            };
+    /// Puts the camera at `pos` looking along `front`, keeping the mouse look in sync
+    pub fn look(&mut self, pos: Vec3, front: Vec3) {
+        self.camera.front = front;
+        self.camera.pitch = front.y.clamp(-1.0, 1.0).asin().to_degrees();
+        self.camera.yaw = front.z.atan2(front.x).to_degrees();
 
-            match input.virtual_keycode {
-                Some(winit::event::VirtualKeyCode::W) => {
-                    keymap[0] = action;
-                },
-                Some(winit::event::VirtualKeyCode::A) => {
-                    keymap[1] = action;
-                },
-                Some(winit::event::VirtualKeyCode::S) => {
-                    keymap[2] = action;
-                },
-                Some(winit::event::VirtualKeyCode::D) => {
-                    keymap[3] = action;
-                },
-                Some(winit::event::VirtualKeyCode::Space) => {
-                    keymap[4] = action;
-                },
-                Some(winit::event::VirtualKeyCode::LControl) => {
-                    keymap[5] = action;
-                },
-                Some(winit::event::VirtualKeyCode::LShift) => {
-                    keymap[6] = action;
-                },
-                _ => ()
+        self.camera.right = Vec3::Y.cross(-self.camera.front).normalize();
+        self.camera.up = self.camera.front.cross(self.camera.right).normalize();
+        self.camera.update(pos);
+    }
+
+    /// Yaw and pitch in degrees, like the mouse sets them
+    pub fn set_rotation(&mut self, yaw: f32, pitch: f32) {
+        let (yaw, pitch) = (yaw.to_radians(), pitch.clamp(-89.0, 89.0).to_radians());
+        let front = Vec3::new(yaw.cos() * pitch.cos(), pitch.sin(), yaw.sin() * pitch.cos());
+        self.look(self.camera.pos, front);
+    }
+
+    /// Jumps there, dropping any scripted moves
+    pub fn teleport(&mut self, pos: Vec3) {
+        self.moves.clear();
+        self.look(pos, self.camera.front);
+        self.sync_movement();
+    }
+
+    /// Queues a smooth move after the ones already queued
+    pub fn move_to(&mut self, step: CameraMove) {
+        if !self.moves.is_moving() {
+            self.sync_movement();
+        }
+
+        self.moves.push(self.camera.pos, step);
+    }
+
+    /// Drops the scripted moves, leaving the camera where it got to
+    pub fn stop_moving(&mut self) {
+        if self.moves.is_moving() {
+            self.moves.clear();
+            self.sync_movement();
+        }
+    }
+
+    /// Makes the current movement mode continue from wherever the camera is now
+    fn sync_movement(&mut self) {
+        /* the fly camera works on the negated position */
+        self.camera.goal = -self.camera.pos;
+        self.cam_sod = SecondOrderDynamics::new(2.75, 0.75, 0.0, self.camera.goal);
+        self.player = Player::new(self.camera.pos);
+    }
+
+    pub fn toggle_move_mode(&mut self) {
+        self.move_mode = match self.move_mode {
+            MoveMode::Fly => MoveMode::Walk,
+            MoveMode::Walk => MoveMode::Fly,
+        };
+        self.sync_movement();
+    }
+
+    /// F9 starts a recording and saves it when pressed again
+    pub fn toggle_recording(&mut self) {
+        if let Some(path) = self.path.stop_recording() {
+            match path.save(Path::new(CAMERA_PATH_FILE)) {
+                Ok(()) => println!("saved {} keyframes to {CAMERA_PATH_FILE}", path.keyframes.len()),
+                Err(e) => println!("failed to save the camera path: {e}"),
             }
+        } else {
+            self.path.start_recording();
+        }
+    }
 
-            *keymap
+    /// F10 plays the last saved recording, or stops the playback
+    pub fn toggle_playback(&mut self) {
+        if self.path.is_playing() {
+            self.path = PathState::Idle;
+            self.sync_movement();
+            return;
         }
 
-        _ => *keymap,
+        match CameraPath::load(Path::new(CAMERA_PATH_FILE)) {
+            Ok(path) => self.path.play(path),
+            Err(e) => println!("failed to load the camera path: {e}"),
+        }
     }
+}
 
+/// Movement actions, see `input::Bindings`
+pub fn register_actions(bindings: &mut Bindings) {
+    use winit::event::VirtualKeyCode::*;
+
+    bindings.register("move_forward", &[W]);
+    bindings.register("move_left", &[A]);
+    bindings.register("move_back", &[S]);
+    bindings.register("move_right", &[D]);
+    bindings.register("move_up", &[Space]);
+    bindings.register("move_down", &[LControl]);
+    bindings.register("sprint", &[LShift]);
+    bindings.register("jump", &[Space]);
+    bindings.register("crouch", &[LControl]);
+    bindings.register("toggle_walk", &[V]);
+    bindings.register("record_path", &[F9]);
+    bindings.register("play_path", &[F10]);
 }
 
-pub fn set_goal_according_to_input(cam: &mut Camera, keymap: [bool; 7]) {
+pub fn set_goal_according_to_input(cam: &mut Camera, bindings: &Bindings) {
     let mut speed = cam.speed;
-    if keymap[6] {
+    if bindings.is_down("sprint") {
         speed = cam.speed * 20.0;
     }
 
-    if keymap[0] {
+    if bindings.is_down("move_forward") {
         cam.goal -= speed * cam.dt * cam.front;
     }
-    if keymap[1] {
+    if bindings.is_down("move_left") {
         cam.goal -= speed * cam.dt * Vec3::cross(cam.front, cam.up);
     }
-    if keymap[2] {
+    if bindings.is_down("move_back") {
         cam.goal += speed * cam.dt * cam.front;
     }
-    if keymap[3] {
+    if bindings.is_down("move_right") {
         cam.goal += speed * cam.dt * Vec3::cross(cam.front, cam.up);
     }
-    if keymap[4] {
+    if bindings.is_down("move_up") {
         cam.goal -= speed * cam.dt * cam.up;
     }
-    if keymap[5] {
+    if bindings.is_down("move_down") {
         cam.goal += speed * cam.dt * cam.up;
     }
 }
 
+pub fn walk_input(cam: &Camera, bindings: &Bindings) -> WalkInput {
+    let forward = Vec3::new(cam.front.x, 0.0, cam.front.z).normalize_or_zero();
+    let left = Vec3::cross(cam.front, cam.up);
+    let left = Vec3::new(left.x, 0.0, left.z).normalize_or_zero();
+
+    let mut wish_dir = Vec3::ZERO;
+    if bindings.is_down("move_forward") { wish_dir += forward; }
+    if bindings.is_down("move_left") { wish_dir += left; }
+    if bindings.is_down("move_back") { wish_dir -= forward; }
+    if bindings.is_down("move_right") { wish_dir -= left; }
+
+    WalkInput {
+        wish_dir,
+        jump: bindings.is_down("jump"),
+        crouch: bindings.is_down("crouch"),
+        sprint: bindings.is_down("sprint"),
+    }
+}
+
+/// `vk` is only there for imgui, which needs the window
 pub fn get_cmd_bufs(
     vk: Arc<Vk>, 
+    gpu: Arc<Gpu>,
     renderer: &mut Renderer,
     imgui_renderer: &mut ImGui,
     presenter: &Presenter,
-    pipeline: Arc<GraphicsPipeline>,
+    pipelines: &Pipelines,
     world: &mut World,
     rp: Arc<RenderPass>,
 ) -> Vec<CommandBufferType> {
+    let start = Instant::now();
     let mut cmd_bufs = vec![];
-    let mut meshes = world.query::<&MeshComponent>();
 
-    let ubo = VkBuffer::uniform(vk.allocators.clone(), vs::Camera {
-        view: renderer.camera.get_view(),
-        proj: renderer.camera.get_proj(),
-    });
+    let descriptors = scene_descriptors(gpu.clone(), renderer, pipelines, world);
 
-    let camera_desc_set = descriptor_set(
-        vk.clone(), 
-        0, 
-        pipeline.clone(), 
-        [WriteDescriptorSet::buffer(0, ubo.content.clone())]
-    ).0;
+    if renderer.secondary_chunks {
+        renderer.prepare_frame_uniforms(gpu.clone(), pipelines, presenter.framebuffers.len(), world);
+    }
 
     let imgui_renderpasses = imgui_renderer.get_renderpasses(
         presenter.images.clone(),
@@ -144,51 +307,46 @@ pub fn get_cmd_bufs(
     let mut i = 0;
 
     for framebuffer in &presenter.framebuffers {
-        let mut builder = VkBuilder::new_multiple(vk.clone());
+        let mut builder = gpu.builder_multiple();
+
+        let contents = if renderer.secondary_chunks {
+            /* has to happen outside of the render pass */
+            renderer.frame_uniforms[i].update(&mut builder.0, renderer, world);
+
+            SubpassContents::SecondaryCommandBuffers
+        } else {
+            SubpassContents::Inline
+        };
+
+        let extent = [framebuffer.extent()[0], framebuffer.extent()[1]];
 
         builder.0
             .begin_render_pass(
                 RenderPassBeginInfo {
-                    clear_values: vec![Some([0.1, 0.2, 0.3, 1.0].into()), Some(1.0.into())],
+                    clear_values: vec![Some(descriptors.clear_color.into()), Some(1.0.into())],
                     ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
                 },
                 SubpassBeginInfo {
-                    contents: SubpassContents::Inline,
+                    contents,
                     ..Default::default()
                 },
             )
             .unwrap();
-    
-        builder.0
-            .bind_pipeline_graphics(pipeline.clone())
-            .unwrap()            
-            .bind_descriptor_sets(
-                vulkano::pipeline::PipelineBindPoint::Graphics, 
-                pipeline.layout().clone(), 
-                0, 
-                camera_desc_set.clone(),
-            )
-            .unwrap();
 
-        for mesh in &renderer.meshes {
-            mesh.build_commands(vk.clone(), &mut builder.0, pipeline.clone());
-        }
+        if renderer.secondary_chunks {
+            let inheritance = inheritance_info(rp.clone());
 
-        for mesh in meshes.iter(&world) {
-            let mesh = Mesh::new(vk.clone(), &mesh.vertices, &mesh.indices);
-            mesh.build_commands(vk.clone(), &mut builder.0, pipeline.clone());
-        }
-    
-        let chunkmeshes = &mut world.resource_mut::<ChunkWorld>().meshes;
-        for mesh in chunkmeshes.values_mut().filter_map(Option::as_mut) {
-            if mesh.visible {
-                mesh.bind_buffers(&mut builder.0);
-                builder.0
-                    .draw_indexed_indirect(mesh.get_indb(&vk))
-                    .unwrap();
-            }
+            let mut secondaries = chunk_secondaries(gpu.clone(), renderer, i, extent, pipelines, inheritance.clone(), world);
+
+            /* everything else changes every frame anyway */
+            let mut extras = gpu.builder_secondary(inheritance);
+            record_extras(gpu.clone(), &mut extras, extent, renderer, pipelines, &descriptors, world);
+            secondaries.push(extras.build().unwrap());
+
+            builder.0.execute_commands_from_vec(secondaries).unwrap();
+        } else {
+            record_scene(gpu.clone(), &mut builder.0, extent, renderer, pipelines, &descriptors, world);
         }
-        
     
         builder.0.end_render_pass(Default::default()).unwrap();
     
@@ -221,91 +379,434 @@ pub fn get_cmd_bufs(
 
     // renderer.pool.join();
 
+    renderer.record_time = start.elapsed();
+
     cmd_bufs
 }
 
-pub fn commands(
-    vk: &Arc<Vk>, 
-    framebuffer: &Arc<Framebuffer>,
+/// Descriptor sets bound at set 0 by the scene pipelines
+pub struct SceneDescriptors {
+    pub chunk: Arc<PersistentDescriptorSet>,
+    pub mesh: Arc<PersistentDescriptorSet>,
+    pub lines: Arc<PersistentDescriptorSet>,
+    pub instanced: Arc<PersistentDescriptorSet>,
+    pub clear_color: [f32; 4],
+}
+
+/// Contents of the camera, lights and fog uniforms for this frame
+pub fn scene_uniforms(renderer: &Renderer, world: &World) -> (vs::Camera, fs::Lights, fs::Fog) {
+    let camera = vs::Camera {
+        view: renderer.camera.get_view(),
+        proj: renderer.camera.get_proj(),
+    };
+
+    (
+        camera,
+        world.resource::<Lights>().get_ubo_data(&renderer.camera),
+        world.resource::<Fog>().get_ubo_data(),
+    )
+}
+
+pub fn scene_descriptors(
+    gpu: Arc<Gpu>,
+    renderer: &Renderer,
+    pipelines: &Pipelines,
+    world: &World,
+) -> SceneDescriptors {
+    let (camera, lights, fog) = scene_uniforms(renderer, world);
+
+    let ubo = VkBuffer::uniform(gpu.allocators.clone(), camera);
+    let lights_ubo = VkBuffer::uniform(gpu.allocators.clone(), lights);
+    let fog_ubo = VkBuffer::uniform(gpu.allocators.clone(), fog);
+
+    let chunk = gpu.descriptor_set(
+        0,
+        pipelines.chunk.clone(), 
+        [
+            WriteDescriptorSet::buffer(0, ubo.content.clone()),
+            WriteDescriptorSet::buffer(1, lights_ubo.content.clone()),
+            WriteDescriptorSet::buffer(2, fog_ubo.content.clone()),
+        ]
+    );
+
+    let mesh = gpu.descriptor_set(
+        0,
+        pipelines.mesh.clone(), 
+        [WriteDescriptorSet::buffer(0, ubo.content.clone())]
+    );
+
+    let lines = gpu.descriptor_set(
+        0,
+        pipelines.lines.clone(), 
+        [WriteDescriptorSet::buffer(0, ubo.content.clone())]
+    );
+
+    let instanced = gpu.descriptor_set(
+        0,
+        pipelines.instanced.clone(), 
+        [
+            WriteDescriptorSet::buffer(0, ubo.content.clone()),
+            WriteDescriptorSet::buffer(2, fog_ubo.content.clone()),
+        ]
+    );
+
+    SceneDescriptors {
+        chunk,
+        mesh,
+        lines,
+        instanced,
+        clear_color: world.resource::<Fog>().clear_color(),
+    }
+}
+
+/// Scene uniforms that live as long as the swapchain image they're used with,
+/// so that recorded chunk command buffers can keep pointing at them. Their
+/// contents get replaced with `update_buffer` at the start of every frame
+pub struct FrameUniforms {
+    camera: Subbuffer<vs::Camera>,
+    lights: Subbuffer<fs::Lights>,
+    fog: Subbuffer<fs::Fog>,
+    chunk_set: Arc<PersistentDescriptorSet>,
+}
+
+impl FrameUniforms {
+    pub fn new(gpu: Arc<Gpu>, pipeline: Arc<GraphicsPipeline>, renderer: &Renderer, world: &World) -> Self {
+        let (camera, lights, fog) = scene_uniforms(renderer, world);
+
+        let camera = uniform_buffer(&gpu, camera);
+        let lights = uniform_buffer(&gpu, lights);
+        let fog = uniform_buffer(&gpu, fog);
+
+        let chunk_set = gpu.descriptor_set(
+            0,
+            pipeline, 
+            [
+                WriteDescriptorSet::buffer(0, camera.clone()),
+                WriteDescriptorSet::buffer(1, lights.clone()),
+                WriteDescriptorSet::buffer(2, fog.clone()),
+            ]
+        );
+
+        Self {
+            camera,
+            lights,
+            fog,
+            chunk_set,
+        }
+    }
+
+    pub fn update(&self, builder: &mut BuilderType, renderer: &Renderer, world: &World) {
+        let (camera, lights, fog) = scene_uniforms(renderer, world);
+
+        builder
+            .update_buffer(self.camera.clone(), Box::new(camera))
+            .unwrap()
+            .update_buffer(self.lights.clone(), Box::new(lights))
+            .unwrap()
+            .update_buffer(self.fog.clone(), Box::new(fog))
+            .unwrap();
+    }
+}
+
+fn uniform_buffer<T: BufferContents>(gpu: &Arc<Gpu>, data: T) -> Subbuffer<T> {
+    Buffer::from_data(
+        gpu.allocators.memory.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER | BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        data,
+    )
+    .expect("failed to create buffer")
+}
+
+fn inheritance_info(rp: Arc<RenderPass>) -> CommandBufferInheritanceInfo {
+    CommandBufferInheritanceInfo {
+        render_pass: Some(
+            CommandBufferInheritanceRenderPassInfo {
+                subpass: Subpass::from(rp, 0).unwrap(),
+                framebuffer: None,
+            }
+            .into(),
+        ),
+        ..Default::default()
+    }
+}
+
+/// The wireframe pipeline shares its layout with the regular one
+fn chunk_pipeline(pipelines: &Pipelines, debug: &DebugView) -> Arc<GraphicsPipeline> {
+    match (&pipelines.chunk_wireframe, debug.wireframe) {
+        (Some(wireframe), true) => wireframe.clone(),
+        _ => pipelines.chunk.clone(),
+    }
+}
+
+/// Expects the chunk pipeline and its descriptor set to be bound
+fn draw_chunk<L>(
+    gpu: &Arc<Gpu>,
+    builder: &mut AutoCommandBufferBuilder<L, Arc<StandardCommandBufferAllocator>>,
+    mesh: &mut ChunkMesh,
+    quad_ebo: &QuadIndexBuffer,
     pipeline: &Arc<GraphicsPipeline>,
-    camera_desc_set: &Arc<PersistentDescriptorSet>,
-    // chunkmeshes: &mut Arc<Mutex<HashMap<(isize, isize, isize), Option<ChunkMesh>>>>,
-    imgui_renderpass: &VkSecRenderpass,
+    debug: &DebugView,
+) {
+    mesh.bind_buffers(builder, quad_ebo);
+    builder
+        .push_constants(pipeline.layout().clone(), 0, debug.push_constants(mesh.lod, !mesh.visible))
+        .unwrap()
+        .draw_indexed_indirect(mesh.get_indb(gpu))
+        .unwrap();
+}
+
+/// Returns the command buffers of every chunk to draw on framebuffer
+/// `image_i`, only recording the ones that are missing or out of date
+fn chunk_secondaries(
+    gpu: Arc<Gpu>,
+    renderer: &Renderer,
+    image_i: usize,
+    extent: [u32; 2],
+    pipelines: &Pipelines,
+    inheritance: CommandBufferInheritanceInfo,
+    world: &mut World,
+) -> Vec<Arc<dyn SecondaryCommandBufferAbstract>> {
+    let debug = *world.resource::<DebugView>();
+    let pipeline = chunk_pipeline(pipelines, &debug);
+    let uniforms = &renderer.frame_uniforms[image_i];
+
+    let mut chunkworld = world.resource_mut::<ChunkWorld>();
+    let chunkworld = &mut *chunkworld;
+
+    let mut cmd_bufs: Vec<Arc<dyn SecondaryCommandBufferAbstract>> = vec![];
+
+    for mesh in chunkworld.meshes.values_mut().filter_map(Option::as_mut) {
+        if !mesh.visible && !debug.show_culled {
+            continue;
+        }
+
+        /* everything baked into the recorded commands */
+        let mut hasher = DefaultHasher::new();
+        (Arc::as_ptr(&pipeline) as usize, renderer.uniforms_generation, extent, debug.mode, mesh.visible).hash(&mut hasher);
+        let key = hasher.finish();
+
+        /* one per swapchain image, like the uniforms */
+        let images = renderer.frame_uniforms.len();
+        if mesh.cbo_key != key || mesh.cbo.len() != images {
+            mesh.cbo_key = key;
+            mesh.cbo = vec![None; images];
+        }
+
+        if mesh.cbo[image_i].is_none() {
+            /* dynamic state isn't inherited from the primary */
+            let mut builder = gpu.builder_secondary(inheritance.clone());
+            set_viewport(&mut builder, extent);
+            builder
+                .bind_pipeline_graphics(pipeline.clone())
+                .unwrap()
+                .bind_descriptor_sets(
+                    vulkano::pipeline::PipelineBindPoint::Graphics, 
+                    pipeline.layout().clone(), 
+                    0, 
+                    uniforms.chunk_set.clone(),
+                )
+                .unwrap();
+
+            draw_chunk(&gpu, &mut builder, mesh, &chunkworld.quad_ebo, &pipeline, &debug);
+
+            mesh.cbo[image_i] = Some(builder.build().unwrap());
+        }
 
-    cmd_bufs: &mut Vec<CommandBufferType>,
-    i: &mut usize,
+        cmd_bufs.push(mesh.cbo[image_i].clone().unwrap());
+    }
+
+    cmd_bufs
+}
+
+/// Draws the chunks and the meshes. A render pass the pipelines were made
+/// for has to be started already
+pub fn record_scene(
+    gpu: Arc<Gpu>,
+    builder: &mut BuilderType,
+    extent: [u32; 2],
+    renderer: &Renderer,
+    pipelines: &Pipelines,
+    descriptors: &SceneDescriptors,
+    world: &mut World,
 ) {
-    let mut builder = VkBuilder::new_multiple(vk.clone());
+    let debug = *world.resource::<DebugView>();
+    let pipeline = chunk_pipeline(pipelines, &debug);
+
+    set_viewport(builder, extent);
+    builder
+        .bind_pipeline_graphics(pipeline.clone())
+        .unwrap()            
+        .bind_descriptor_sets(
+            vulkano::pipeline::PipelineBindPoint::Graphics, 
+            pipeline.layout().clone(), 
+            0, 
+            descriptors.chunk.clone(),
+        )
+        .unwrap();
 
-    builder.0
-        .begin_render_pass(
-            RenderPassBeginInfo {
-                clear_values: vec![Some([0.1, 0.2, 0.3, 1.0].into()), Some(1.0.into())],
-                ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
-            },
-            SubpassBeginInfo {
-                contents: SubpassContents::Inline,
-                ..Default::default()
-            },
+    let mut chunkworld = world.resource_mut::<ChunkWorld>();
+    let chunkworld = &mut *chunkworld;
+    for mesh in chunkworld.meshes.values_mut().filter_map(Option::as_mut) {
+        if mesh.visible || debug.show_culled {
+            draw_chunk(&gpu, builder, mesh, &chunkworld.quad_ebo, &pipeline, &debug);
+        }
+    }
+
+    record_extras(gpu, builder, extent, renderer, pipelines, descriptors, world);
+}
+
+/// Everything but the chunks: debug lines, meshes and instances
+pub fn record_extras<L>(
+    gpu: Arc<Gpu>,
+    builder: &mut AutoCommandBufferBuilder<L, Arc<StandardCommandBufferAllocator>>,
+    extent: [u32; 2],
+    renderer: &Renderer,
+    pipelines: &Pipelines,
+    descriptors: &SceneDescriptors,
+    world: &mut World,
+) {
+    let debug = *world.resource::<DebugView>();
+    let window_state = *world.resource::<WindowState>();
+    let chunkworld = world.resource::<ChunkWorld>();
+
+    set_viewport(builder, extent);
+
+    let mut lines = vec![];
+    if debug.chunk_bounds {
+        for (k, mesh) in &chunkworld.meshes {
+            let lod = match mesh {
+                Some(mesh) => mesh.lod,
+                None => chunkworld.chunk(k).map_or(0, |chunk| chunk.lod),
+            };
+
+            lines.extend(debug::chunk_bounds(*k, lod, !ChunkCuller::is_visible(*k, &renderer.camera, &window_state)));
+        }
+    }
+
+    /* a bit bigger than the voxels so they don't fight with their faces */
+    let outline = |min: IVec3, size: IVec3, color: Vec3| debug::box_lines(min.as_vec3() - 0.005, size.as_vec3() + 0.01, color);
+    if let Some(hit) = &renderer.target {
+        lines.extend(outline(hit.voxel, IVec3::ONE, debug::TARGET_COLOR));
+    }
+
+    let clipboard = world.resource::<Clipboard>();
+    if let Some((min, size)) = clipboard.selection() {
+        lines.extend(outline(min, size, debug::SELECTION_COLOR));
+    }
+    if let (Some(schematic), Some(hit)) = (&clipboard.schematic, &renderer.target) {
+        lines.extend(outline(editing::paste_origin(hit), schematic.size, debug::PASTE_COLOR));
+    }
+
+    if !lines.is_empty() {
+        let vertex_count = lines.len() as u32;
+        let vbo = VkIterBuffer::vertex(gpu.allocators.clone(), lines);
+        let ibo = VkIterBuffer::vertex(gpu.allocators.clone(), vec![InstanceData { ofs: [0.0; 3] }]);
+
+        builder
+            .bind_pipeline_graphics(pipelines.lines.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                vulkano::pipeline::PipelineBindPoint::Graphics, 
+                pipelines.lines.layout().clone(), 
+                0, 
+                descriptors.lines.clone(),
+            )
+            .unwrap()
+            .bind_vertex_buffers(0, (vbo.content, ibo.content))
+            .unwrap()
+            .draw(vertex_count, 1, 0, 0)
+            .unwrap();
+    }
+
+    let mesh_pipeline = pipelines.mesh.clone();
+
+    builder
+        .bind_pipeline_graphics(mesh_pipeline.clone())
+        .unwrap()            
+        .bind_descriptor_sets(
+            vulkano::pipeline::PipelineBindPoint::Graphics, 
+            mesh_pipeline.layout().clone(), 
+            0, 
+            descriptors.mesh.clone(),
         )
         .unwrap();
 
-    //builder.0
-    //    .bind_pipeline_graphics(pipeline.clone())
-    //    .unwrap()            
-    //    .bind_descriptor_sets(
-    //        vulkano::pipeline::PipelineBindPoint::Graphics, 
-    //        pipeline.layout().clone(), 
-    //        0, 
-    //        camera_desc_set.clone(),
-    //    )
-    //    .unwrap();
-        // .bind_descriptor_sets(
-        //     vulkano::pipeline::PipelineBindPoint::Graphics, 
-        //     pipeline.layout().clone(), 
-        //     1, 
-        //     Mesh::get_ubo(&Mesh::, vk),
-        // )
-        // .unwrap();
-
-    // let mut chunkmeshes_lock = chunkmeshes.lock().unwrap();
-    // for mesh in chunkmeshes_lock.values_mut() {
-    //     if let Some(mesh) = mesh {
-    //         mesh.bind_buffers(&mut builder.0);
-    //         builder.0.draw_indexed_indirect(
-    //             mesh.get_indb(vk.clone())
-    //         ).unwrap();
-    //     }
-    // }
-    // drop(chunkmeshes_lock);
-
-    builder.0.end_render_pass(Default::default()).unwrap();
-
-    /* ----- RENDER IMGUI ------ */
-
-    let render_pass = imgui_renderpass;
-    builder.0.begin_render_pass(
-        RenderPassBeginInfo {
-            clear_values: vec![None],
-            render_pass: render_pass.rp.clone(),
-            ..RenderPassBeginInfo::framebuffer(render_pass.framebuffer.clone())
-        },
-        SubpassBeginInfo {
-            contents: SubpassContents::SecondaryCommandBuffers,
-            ..Default::default()
-        },
-    ).expect(&format!("failed to start imgui render pass on framebuffer {:?}", framebuffer));
+    for mesh in &renderer.meshes {
+        draw_mesh(gpu.clone(), builder, mesh_pipeline.clone(), mesh);
+    }
 
-    builder.0.execute_commands(render_pass.cmd_buf.clone()).unwrap();
-    
-    builder.0.end_render_pass(Default::default()).unwrap();
-    
-    
-    cmd_bufs.push(
-        builder.command_buffer()
-    );
+    /* a single draw per prototype, see prototypes::sync */
+    let instanced = pipelines.instanced.clone();
+
+    builder
+        .bind_pipeline_graphics(instanced.clone())
+        .unwrap()
+        .bind_descriptor_sets(
+            vulkano::pipeline::PipelineBindPoint::Graphics, 
+            instanced.layout().clone(), 
+            0, 
+            descriptors.instanced.clone(),
+        )
+        .unwrap();
 
-    *i += 1;
+    for mesh in world.resource::<Prototypes>().meshes.values() {
+        if let Some(instances) = &mesh.instances {
+            builder
+                .bind_vertex_buffers(0, (mesh.vbo.clone(), instances.clone()))
+                .unwrap()
+                .bind_index_buffer(mesh.ebo.clone())
+                .unwrap()
+                .draw_indexed(mesh.ebo.len() as u32, instances.len() as u32, 0, 0, 0)
+                .unwrap();
+        }
+    }
+}
+
+/// The pipelines take the viewport and scissor as dynamic state
+fn set_viewport<L>(builder: &mut AutoCommandBufferBuilder<L, Arc<StandardCommandBufferAllocator>>, extent: [u32; 2]) {
+    builder
+        .set_viewport(0, [window::viewport(extent)].into_iter().collect())
+        .unwrap()
+        .set_scissor(0, [window::scissor(extent)].into_iter().collect())
+        .unwrap();
+}
+
+/// Same as `Mesh::build_commands`, but works with secondary command buffers too
+fn draw_mesh<L>(
+    gpu: Arc<Gpu>,
+    builder: &mut AutoCommandBufferBuilder<L, Arc<StandardCommandBufferAllocator>>,
+    pipeline: Arc<GraphicsPipeline>,
+    mesh: &Mesh,
+) {
+    let ubo = VkBuffer::uniform(gpu.allocators.clone(), mesh.get_model());
+
+    builder
+        .bind_descriptor_sets(
+            vulkano::pipeline::PipelineBindPoint::Graphics, 
+            pipeline.layout().clone(), 
+            1, 
+            gpu.descriptor_set(
+                1,
+                pipeline.clone(), 
+                [WriteDescriptorSet::buffer(0, ubo.content.clone())]
+            )
+        )
+        .unwrap()
+        .bind_vertex_buffers(0, 
+            (mesh.vbo.content.clone(), mesh.ibo.content.clone())
+        )
+        .unwrap()
+        .bind_index_buffer(mesh.ebo.content.clone())
+        .unwrap()
+        .draw_indexed(mesh.ebo.content.len() as u32, mesh.ibo.content.len() as u32, 0, 0, 0)
+        .unwrap();
 }
 
 /* someone forgot to implement clone for VkSecRenderpass. i wonder who could it possibly be */
@@ -0,0 +1,367 @@
+use std::{fs::File, io::{self, BufReader, BufWriter, Read, Write}, path::Path};
+
+use bevy_ecs::system::Resource;
+use glam::IVec3;
+use imgui::Ui;
+
+use crate::{geometry::voxel_gen, history::{read_u32, write_u32}, world::{ChunkWorld, Voxel}};
+
+/// Selections bigger than this on any axis can't be copied. Pastes relight and
+/// keep an undo copy of every voxel they touch, so bigger ones stall the frame
+pub const MAX_SIZE: i32 = 64;
+
+/*
+A box of voxels, copied from the world to be pasted elsewhere or shared as a
+file. Voxels are indices into `palette`, which holds their material ids, and
+are ordered by x, then y, then z (`voxel_gen::index_in`, the order of
+`get_pos`, not of `get_index`).
+
+File format, little endian:
+
+    b"CSCH", u32 version
+    u32 size x, y, z
+    u32 palette length, then the material ids
+    u32 run count, then per run: u32 length, u32 palette index
+
+Caves are mostly long runs of rock or air, so run length encoding is enough
+to keep the files small.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schematic {
+    pub size: IVec3,
+    pub palette: Vec<usize>,
+    voxels: Vec<u32>,
+}
+
+impl Schematic {
+    /// The voxels from `a` to `b`, both included. Unloaded ones are copied as air
+    pub fn copy(world: &ChunkWorld, a: IVec3, b: IVec3) -> Result<Self, String> {
+        let min = a.min(b);
+        let size = a.max(b) - min + 1;
+        if size.max_element() > MAX_SIZE {
+            return Err(format!("selection is bigger than {MAX_SIZE} voxels"));
+        }
+
+        let mut schematic = Self {
+            size,
+            palette: vec![0],
+            voxels: vec![0; size.x as usize * size.y as usize * size.z as usize],
+        };
+
+        for idx in 0..schematic.voxels.len() {
+            let pos = voxel_gen::pos_in(size, idx);
+            let id = world.voxel_at(min + pos).map_or(0, |voxel| voxel.id);
+            schematic.voxels[idx] = schematic.palette_index(id);
+        }
+
+        Ok(schematic)
+    }
+
+    fn palette_index(&mut self, id: usize) -> u32 {
+        match self.palette.iter().position(|p| *p == id) {
+            Some(i) => i as u32,
+            None => {
+                self.palette.push(id);
+                self.palette.len() as u32 - 1
+            }
+        }
+    }
+
+    /// Rebuilds the schematic with voxel `pos` taken from `from(pos)` of the old one
+    fn remap(&self, size: IVec3, from: impl Fn(IVec3) -> IVec3) -> Self {
+        let voxels = (0..self.voxels.len())
+            .map(|idx| self.voxels[voxel_gen::index_in(self.size, from(voxel_gen::pos_in(size, idx)))])
+            .collect();
+
+        Self {
+            size,
+            palette: self.palette.clone(),
+            voxels,
+        }
+    }
+
+    /// A quarter turn around the vertical axis
+    pub fn rotated(&self) -> Self {
+        let size = self.size;
+        self.remap(IVec3::new(size.z, size.y, size.x), |p| IVec3::new(p.z, p.y, size.z - 1 - p.x))
+    }
+
+    /// Flipped along x
+    pub fn mirrored(&self) -> Self {
+        let size = self.size;
+        self.remap(size, |p| IVec3::new(size.x - 1 - p.x, p.y, p.z))
+    }
+
+    /// Puts the box down with its lowest corner at `origin`, as one edit.
+    /// Without `include_air`, only the solid voxels are pasted
+    pub fn paste(&self, world: &mut ChunkWorld, origin: IVec3, include_air: bool) {
+        for idx in 0..self.voxels.len() {
+            let pos = voxel_gen::pos_in(self.size, idx);
+            let id = self.palette[self.voxels[idx] as usize];
+            if id != 0 || include_air {
+                world.set_voxel(origin + pos, Voxel { id });
+            }
+        }
+
+        world.history.commit("paste");
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(b"CSCH")?;
+        write_u32(&mut out, 1)?;
+
+        for v in self.size.to_array() {
+            write_u32(&mut out, v as u32)?;
+        }
+
+        write_u32(&mut out, self.palette.len() as u32)?;
+        for id in &self.palette {
+            write_u32(&mut out, *id as u32)?;
+        }
+
+        let mut runs: Vec<(u32, u32)> = vec![];
+        for voxel in &self.voxels {
+            match runs.last_mut() {
+                Some((length, run)) if run == voxel => *length += 1,
+                _ => runs.push((1, *voxel)),
+            }
+        }
+
+        write_u32(&mut out, runs.len() as u32)?;
+        for (length, voxel) in runs {
+            write_u32(&mut out, length)?;
+            write_u32(&mut out, voxel)?;
+        }
+
+        out.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {msg}", path.display()));
+        let mut input = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != b"CSCH" || read_u32(&mut input)? != 1 {
+            return Err(invalid("not a schematic"));
+        }
+
+        let size = IVec3::new(read_u32(&mut input)? as i32, read_u32(&mut input)? as i32, read_u32(&mut input)? as i32);
+        if size.min_element() < 1 || size.max_element() > MAX_SIZE {
+            return Err(invalid("bad size"));
+        }
+
+        let mut palette = vec![];
+        for _ in 0..read_u32(&mut input)? {
+            palette.push(read_u32(&mut input)? as usize);
+        }
+
+        let volume = size.x as usize * size.y as usize * size.z as usize;
+        let mut voxels = Vec::with_capacity(volume);
+        for _ in 0..read_u32(&mut input)? {
+            let length = read_u32(&mut input)? as usize;
+            let voxel = read_u32(&mut input)?;
+            if voxel as usize >= palette.len() || voxels.len() + length > volume {
+                return Err(invalid("bad voxel data"));
+            }
+            voxels.extend(std::iter::repeat(voxel).take(length));
+        }
+        if voxels.len() != volume {
+            return Err(invalid("voxel data is too short"));
+        }
+
+        Ok(Self { size, palette, voxels })
+    }
+}
+
+/// The selection, what got copied, and where it goes
+#[derive(Resource)]
+pub struct Clipboard {
+    /// corners of the selection, both included
+    pub corners: [Option<IVec3>; 2],
+    pub schematic: Option<Schematic>,
+    /// whether pasting also carves out the air of the schematic
+    pub include_air: bool,
+    pub file: String,
+    pub show_ui: bool,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self {
+            corners: [None, None],
+            schematic: None,
+            include_air: true,
+            file: String::from("selection.schem"),
+            show_ui: false,
+        }
+    }
+}
+
+impl Clipboard {
+    /// The first press sets one corner, the second the other one, the third starts over
+    pub fn select_corner(&mut self, pos: IVec3) {
+        self.corners = match self.corners {
+            [Some(a), None] => [Some(a), Some(pos)],
+            _ => [Some(pos), None],
+        };
+    }
+
+    /// Lowest corner and size of the selection, a single voxel while only one corner is set
+    pub fn selection(&self) -> Option<(IVec3, IVec3)> {
+        match self.corners {
+            [Some(a), Some(b)] => Some((a.min(b), a.max(b) - a.min(b) + 1)),
+            [Some(a), None] => Some((a, IVec3::ONE)),
+            _ => None,
+        }
+    }
+
+    pub fn copy(&mut self, world: &ChunkWorld) {
+        let [Some(a), Some(b)] = self.corners else {
+            println!("select two corners to copy");
+            return;
+        };
+
+        match Schematic::copy(world, a, b) {
+            Ok(schematic) => self.schematic = Some(schematic),
+            Err(e) => println!("failed to copy: {e}"),
+        }
+    }
+
+    pub fn paste(&self, world: &mut ChunkWorld, origin: IVec3) {
+        if let Some(schematic) = &self.schematic {
+            schematic.paste(world, origin, self.include_air);
+        }
+    }
+
+    pub fn rotate(&mut self) {
+        self.schematic = self.schematic.as_ref().map(Schematic::rotated);
+    }
+
+    pub fn mirror(&mut self) {
+        self.schematic = self.schematic.as_ref().map(Schematic::mirrored);
+    }
+
+    pub fn ui(&mut self, ui: &Ui, world: &mut ChunkWorld) {
+        if !self.show_ui {
+            return;
+        }
+
+        ui.window("clipboard").build(|| {
+            match self.corners {
+                [Some(a), Some(b)] => ui.text(format!("selected {a} to {b}")),
+                [Some(a), None] => ui.text(format!("selected {a}, G on the other corner")),
+                _ => ui.text("G on a voxel selects a corner"),
+            }
+            if ui.button("copy") {
+                self.copy(world);
+            }
+            ui.same_line();
+            if ui.button("clear selection") {
+                self.corners = [None, None];
+            }
+
+            ui.separator();
+            match &self.schematic {
+                Some(schematic) => ui.text(format!("clipboard: {} with {} materials", schematic.size, schematic.palette.len())),
+                None => ui.text("clipboard is empty"),
+            }
+            if ui.button("rotate") {
+                self.rotate();
+            }
+            ui.same_line();
+            if ui.button("mirror") {
+                self.mirror();
+            }
+            ui.checkbox("paste air", &mut self.include_air);
+
+            ui.separator();
+            ui.input_text("file", &mut self.file).build();
+            if ui.button("save") {
+                if let Some(schematic) = &self.schematic {
+                    match schematic.save(Path::new(&self.file)) {
+                        Ok(()) => println!("saved schematic to {}", self.file),
+                        Err(e) => println!("failed to save schematic: {e}"),
+                    }
+                }
+            }
+            ui.same_line();
+            if ui.button("load") {
+                match Schematic::load(Path::new(&self.file)) {
+                    Ok(schematic) => self.schematic = Some(schematic),
+                    Err(e) => println!("failed to load schematic: {e}"),
+                }
+            }
+        });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use glam::ivec3;
+
+    use super::Schematic;
+
+    /// Every voxel different, so any voxel out of place shows
+    fn schematic() -> Schematic {
+        let size = ivec3(3, 2, 5);
+        let volume = (size.x * size.y * size.z) as usize;
+
+        Schematic {
+            size,
+            palette: (0..volume).map(|id| id * 2).collect(),
+            voxels: (0..volume as u32).collect(),
+        }
+    }
+
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{name}_{}.schem", std::process::id()))
+    }
+
+    #[test]
+    fn four_rotations_are_the_identity() {
+        let schematic = schematic();
+        let once = schematic.rotated();
+
+        assert_eq!(once.size, ivec3(5, 2, 3));
+        assert_ne!(once, schematic);
+        assert_eq!(once.rotated().rotated().rotated(), schematic);
+    }
+
+    #[test]
+    fn mirroring_twice_is_the_identity() {
+        let schematic = schematic();
+
+        assert_ne!(schematic.mirrored(), schematic);
+        assert_eq!(schematic.mirrored().mirrored(), schematic);
+    }
+
+    #[test]
+    fn saved_schematics_load_the_same() {
+        let path = temp_file("schematic_round_trip");
+        /* with runs longer than a voxel */
+        let mut schematic = schematic();
+        schematic.voxels[10..20].fill(3);
+
+        schematic.save(&path).unwrap();
+        let loaded = Schematic::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), schematic);
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let path = temp_file("schematic_truncated");
+        schematic().save(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        for len in [0, 6, 20, bytes.len() - 4, bytes.len() - 1] {
+            std::fs::write(&path, &bytes[..len]).unwrap();
+            assert!(Schematic::load(&path).is_err(), "{len} bytes loaded");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+use std::{collections::VecDeque, sync::{Arc, Mutex}};
+
+use bevy_ecs::{system::Resource, world::World};
+use glam::{vec3, Vec3};
+use mlua::{FromLua, Lua, MetaMethod, Table, UserData, UserDataFields, UserDataMethods};
+
+use crate::{brush::{Brush, BrushOp, BrushShape, BrushStroke}, fog::{Fog, FogCommand, FogMode}, lights::{LightCommand, Lights, PointLight}, mesh_spawner::{SpawnCommand, SpawnCommandBuffer, SPHERE_COLOR}, world::ChunkWorld};
+
+/// `color(r, g, b)` or `color("#ff8800")` in Lua
+#[derive(Clone, Copy, Debug, FromLua)]
+pub struct Color(pub Vec3);
+
+impl Color {
+    fn parse(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if hex.len() != 6 {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok().map(|c| c as f32 / 255.0);
+        Some(Color(vec3(channel(0)?, channel(2)?, channel(4)?)))
+    }
+}
+
+impl UserData for Color {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("r", |_, color| Ok(color.0.x));
+        fields.add_field_method_get("g", |_, color| Ok(color.0.y));
+        fields.add_field_method_get("b", |_, color| Ok(color.0.z));
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(MetaMethod::ToString, |_, color, ()| Ok(format!("color({}, {}, {})", color.0.x, color.0.y, color.0.z)));
+    }
+}
+
+/// Everything a script can ask the engine to do, queued and applied in order
+#[derive(Clone, Debug, FromLua)]
+pub enum ScriptCommand {
+    Spawn(SpawnCommand),
+    Light(LightCommand),
+    Fog(FogCommand),
+    Brush(BrushStroke),
+    Print(String),
+}
+
+impl UserData for ScriptCommand {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(MetaMethod::ToString, |_, command, ()| Ok(format!("{command:?}")));
+    }
+}
+
+/*
+Commands pushed by Lua, waiting for the next frame. Lua lives outside of the
+bevy world, so the queue is shared: the resource and the Lua functions hold
+the same one. `apply` drains all of it every frame.
+*/
+#[derive(Resource, Clone, Default)]
+pub struct ScriptQueue(Arc<Mutex<VecDeque<ScriptCommand>>>);
+
+impl ScriptQueue {
+    pub fn push(&self, command: ScriptCommand) {
+        self.0.lock().unwrap().push_back(command);
+    }
+
+    pub fn drain(&self) -> Vec<ScriptCommand> {
+        self.0.lock().unwrap().drain(..).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+/// Runs everything the scripts queued since the last frame
+pub fn apply(world: &mut World) {
+    let commands = world.resource::<ScriptQueue>().drain();
+    let mut edited = false;
+
+    for command in commands {
+        match command {
+            ScriptCommand::Spawn(command) => world.resource_mut::<SpawnCommandBuffer>().commands.push(command),
+            ScriptCommand::Light(command) => world.resource_mut::<Lights>().apply(command),
+            ScriptCommand::Fog(command) => world.resource_mut::<Fog>().apply(command),
+            ScriptCommand::Brush(stroke) => {
+                /* queued by a callback, often `on_edit` itself */
+                world.resource_mut::<ChunkWorld>().without_edit_events(|chunkworld| stroke.brush.apply(chunkworld, stroke.center));
+                edited = true;
+            }
+            ScriptCommand::Print(text) => println!("script.lua: {text}"),
+        }
+    }
+
+    /* one undo step for everything a script did in a frame */
+    if edited {
+        world.resource_mut::<ChunkWorld>().history.commit("script");
+    }
+}
+
+/// `brush { x, y, z, shape, op, radius, falloff, material }`, unset fields keep their defaults
+fn brush_from_table(args: &Table) -> mlua::Result<BrushStroke> {
+    let mut brush = Brush::default();
+    if let Some(shape) = args.get::<_, Option<String>>("shape")? {
+        brush.shape = match shape.as_str() {
+            "sphere" => BrushShape::Sphere,
+            "box" => BrushShape::Box,
+            "cylinder" => BrushShape::Cylinder,
+            _ => return Err(mlua::Error::RuntimeError(format!("unknown brush shape: {shape}"))),
+        };
+    }
+    if let Some(op) = args.get::<_, Option<String>>("op")? {
+        brush.op = match op.as_str() {
+            "add" => BrushOp::Add,
+            "subtract" => BrushOp::Subtract,
+            "replace" => BrushOp::Replace,
+            "smooth" => BrushOp::Smooth,
+            "noise" => BrushOp::Noise,
+            _ => return Err(mlua::Error::RuntimeError(format!("unknown brush op: {op}"))),
+        };
+    }
+    brush.radius = args.get::<_, Option<f32>>("radius")?.unwrap_or(brush.radius);
+    brush.falloff = args.get::<_, Option<f32>>("falloff")?.unwrap_or(brush.falloff);
+    brush.material = args.get::<_, Option<usize>>("material")?.unwrap_or(brush.material);
+
+    Ok(brush.at(vec3(args.get("x")?, args.get("y")?, args.get("z")?)))
+}
+
+fn fog_mode(mode: &str) -> mlua::Result<FogMode> {
+    match mode {
+        "linear" => Ok(FogMode::Linear),
+        "exponential" | "exp" => Ok(FogMode::Exponential),
+        _ => Err(mlua::Error::RuntimeError(format!("unknown fog mode: {mode}"))),
+    }
+}
+
+/*
+The `command` table builds commands without running them:
+
+    local c = command.sphere(0, 0, 0, 5, color("#ff8800"))
+    push(c)
+
+`push` queues any command. The older globals (`spawn_sphere`, `add_light`,
+`set_fog_mode`, ...) build a command and push it right away.
+*/
+pub fn register(lua: &Lua, queue: ScriptQueue) -> mlua::Result<()> {
+    let globals = lua.globals();
+
+    globals.set("color", lua.create_function(|_, args: mlua::Variadic<mlua::Value>| {
+        match args.as_slice() {
+            [mlua::Value::String(hex)] => {
+                let hex = hex.to_str()?;
+                Color::parse(hex).ok_or_else(|| mlua::Error::RuntimeError(format!("bad color: {hex}")))
+            }
+            [r, g, b] => {
+                let channel = |v: &mlua::Value| v.as_f32().ok_or_else(|| mlua::Error::RuntimeError("color channels are numbers".into()));
+                Ok(Color(vec3(channel(r)?, channel(g)?, channel(b)?)))
+            }
+            _ => Err(mlua::Error::RuntimeError("color(r, g, b) or color(\"#rrggbb\")".into())),
+        }
+    })?)?;
+
+    let command = lua.create_table()?;
+    command.set("sphere", lua.create_function(|_, (x, y, z, r, color): (f32, f32, f32, f32, Option<Color>)| {
+        Ok(ScriptCommand::Spawn(SpawnCommand::Sphere {
+            pos: vec3(x, y, z),
+            radius: r,
+            color: color.map_or(SPHERE_COLOR, |c| c.0),
+        }))
+    })?)?;
+    command.set("clear", lua.create_function(|_, ()| Ok(ScriptCommand::Spawn(SpawnCommand::Clear)))?)?;
+    command.set("light", lua.create_function(|_, (x, y, z, color, radius): (f32, f32, f32, Color, f32)| {
+        Ok(ScriptCommand::Light(LightCommand::Add(PointLight { pos: vec3(x, y, z), color: color.0, radius })))
+    })?)?;
+    command.set("clear_lights", lua.create_function(|_, ()| Ok(ScriptCommand::Light(LightCommand::Clear)))?)?;
+    command.set("fog_color", lua.create_function(|_, color: Color| Ok(ScriptCommand::Fog(FogCommand::Color(color.0))))?)?;
+    command.set("fog_range", lua.create_function(|_, (start, end): (f32, f32)| Ok(ScriptCommand::Fog(FogCommand::Range(start, end))))?)?;
+    command.set("fog_density", lua.create_function(|_, density: f32| Ok(ScriptCommand::Fog(FogCommand::Density(density))))?)?;
+    command.set("fog_mode", lua.create_function(|_, mode: String| Ok(ScriptCommand::Fog(FogCommand::Mode(fog_mode(&mode)?))))?)?;
+    command.set("brush", lua.create_function(|_, args: Table| Ok(ScriptCommand::Brush(brush_from_table(&args)?)))?)?;
+    command.set("print", lua.create_function(|_, text: String| Ok(ScriptCommand::Print(text)))?)?;
+    globals.set("command", command)?;
+
+    let q = queue.clone();
+    globals.set("push", lua.create_function(move |_, command: ScriptCommand| {
+        q.push(command);
+        Ok(())
+    })?)?;
+
+    /* shortcuts */
+    let q = queue.clone();
+    globals.set("spawn_sphere", lua.create_function(move |_, (x, y, z, r): (f32, f32, f32, f32)| {
+        q.push(ScriptCommand::Spawn(SpawnCommand::Sphere { pos: vec3(x, y, z), radius: r, color: SPHERE_COLOR }));
+        Ok(())
+    })?)?;
+    let q = queue.clone();
+    globals.set("clear_world", lua.create_function(move |_, ()| {
+        q.push(ScriptCommand::Spawn(SpawnCommand::Clear));
+        Ok(())
+    })?)?;
+    let q = queue.clone();
+    globals.set("add_light", lua.create_function(move |_, (x, y, z, r, g, b, radius): (f32, f32, f32, f32, f32, f32, f32)| {
+        q.push(ScriptCommand::Light(LightCommand::Add(PointLight { pos: vec3(x, y, z), color: vec3(r, g, b), radius })));
+        Ok(())
+    })?)?;
+    let q = queue.clone();
+    globals.set("clear_lights", lua.create_function(move |_, ()| {
+        q.push(ScriptCommand::Light(LightCommand::Clear));
+        Ok(())
+    })?)?;
+    let q = queue.clone();
+    globals.set("set_fog_color", lua.create_function(move |_, (r, g, b): (f32, f32, f32)| {
+        q.push(ScriptCommand::Fog(FogCommand::Color(vec3(r, g, b))));
+        Ok(())
+    })?)?;
+    let q = queue.clone();
+    globals.set("set_fog_range", lua.create_function(move |_, (start, end): (f32, f32)| {
+        q.push(ScriptCommand::Fog(FogCommand::Range(start, end)));
+        Ok(())
+    })?)?;
+    let q = queue.clone();
+    globals.set("set_fog_density", lua.create_function(move |_, density: f32| {
+        q.push(ScriptCommand::Fog(FogCommand::Density(density)));
+        Ok(())
+    })?)?;
+    let q = queue.clone();
+    globals.set("set_fog_mode", lua.create_function(move |_, mode: String| {
+        q.push(ScriptCommand::Fog(FogCommand::Mode(fog_mode(&mode)?)));
+        Ok(())
+    })?)?;
+    let q = queue.clone();
+    globals.set("carve_sphere", lua.create_function(move |_, (x, y, z, r): (f32, f32, f32, f32)| {
+        let brush = Brush { shape: BrushShape::Sphere, op: BrushOp::Subtract, radius: r, ..Default::default() };
+        q.push(ScriptCommand::Brush(brush.at(vec3(x, y, z))));
+        Ok(())
+    })?)?;
+    let q = queue.clone();
+    globals.set("fill_sphere", lua.create_function(move |_, (x, y, z, r, material): (f32, f32, f32, f32, Option<usize>)| {
+        let brush = Brush { shape: BrushShape::Sphere, op: BrushOp::Add, radius: r, material: material.unwrap_or(1), ..Default::default() };
+        q.push(ScriptCommand::Brush(brush.at(vec3(x, y, z))));
+        Ok(())
+    })?)?;
+    let q = queue.clone();
+    globals.set("brush", lua.create_function(move |_, args: Table| {
+        q.push(ScriptCommand::Brush(brush_from_table(&args)?));
+        Ok(())
+    })?)?;
+
+    Ok(())
+}
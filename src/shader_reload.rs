@@ -0,0 +1,118 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant, SystemTime}};
+
+use shaderc::{Compiler, ShaderKind};
+use vulkano::{device::Device, shader::{ShaderModule, ShaderModuleCreateInfo}};
+
+use crate::pipelines::Shaders;
+
+pub const SHADER_DIR: &str = "shaders";
+
+/* how often the files are checked for changes */
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/*
+Recompiles the shaders in SHADER_DIR when they change on disk, so they can be
+tweaked without rebuilding. Only the GLSL is reloaded: the uniform structs on
+the rust side are still the ones generated at build time, so changing the
+layout of a uniform block still needs a rebuild.
+*/
+pub struct ShaderReloader {
+    pub enabled: bool,
+    /// errors of the last reload, if it failed. The old shaders stay in use
+    pub error: Option<String>,
+
+    compiler: Option<Compiler>,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderReloader {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            error: None,
+            compiler: None,
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Returns freshly compiled shaders if any file changed since the last
+    /// call and all of them compiled
+    pub fn poll(&mut self, device: Arc<Device>) -> Option<Shaders> {
+        if !self.enabled || self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = false;
+        for file in shader_files() {
+            let modified = fs::metadata(&file).and_then(|meta| meta.modified()).ok();
+            if modified.is_some() && self.modified.get(&file) != modified.as_ref() {
+                self.modified.insert(file, modified.unwrap());
+                changed = true;
+            }
+        }
+
+        if !changed {
+            return None;
+        }
+
+        match self.compile_all(device) {
+            Ok(shaders) => {
+                self.error = None;
+                Some(shaders)
+            }
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    fn compile_all(&mut self, device: Arc<Device>) -> Result<Shaders, String> {
+        Ok(Shaders {
+            vs: self.compile(device.clone(), "chunk.vert", ShaderKind::Vertex)?,
+            fs: self.compile(device.clone(), "chunk.frag", ShaderKind::Fragment)?,
+            mesh_vs: self.compile(device.clone(), "mesh.vert", ShaderKind::Vertex)?,
+            mesh_fs: self.compile(device.clone(), "mesh.frag", ShaderKind::Fragment)?,
+            line_vs: self.compile(device.clone(), "line.vert", ShaderKind::Vertex)?,
+            line_fs: self.compile(device.clone(), "line.frag", ShaderKind::Fragment)?,
+            instanced_vs: self.compile(device.clone(), "instanced.vert", ShaderKind::Vertex)?,
+            instanced_fs: self.compile(device.clone(), "instanced.frag", ShaderKind::Fragment)?,
+        })
+    }
+
+    fn compile(&mut self, device: Arc<Device>, name: &str, kind: ShaderKind) -> Result<Arc<ShaderModule>, String> {
+        let path = Path::new(SHADER_DIR).join(name);
+        let src = fs::read_to_string(&path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+
+        if self.compiler.is_none() {
+            self.compiler = Some(Compiler::new().ok_or("failed to create the shader compiler")?);
+        }
+        let compiler = self.compiler.as_ref().unwrap();
+
+        let spirv = compiler
+            .compile_into_spirv(&src, kind, name, "main", None)
+            .map_err(|e| format!("{name}: {e}"))?;
+
+        /* safe as long as shaderc outputs valid spir-v */
+        unsafe {
+            ShaderModule::new(device, ShaderModuleCreateInfo::new(spirv.as_binary()))
+        }
+        .map_err(|e| format!("{name}: {e}"))
+    }
+}
+
+fn shader_files() -> Vec<PathBuf> {
+    [
+        "chunk.vert", "chunk.frag", 
+        "mesh.vert", "mesh.frag", 
+        "line.vert", "line.frag", 
+        "instanced.vert", "instanced.frag",
+    ]
+        .iter()
+        .map(|name| Path::new(SHADER_DIR).join(name))
+        .collect()
+}
@@ -1,50 +1,64 @@
+/*
+the sources live in shaders/ and are compiled in at build time. they can also
+be recompiled while running, see shader_reload
+*/
+
+/* chunk shaders. vertices are packed, see vertex::ChunkVertex */
 pub mod vs {
     vulkano_shaders::shader! {
         ty: "vertex",
-        src: r"
-            #version 460
-
-            layout(location = 0) in vec3 pos; // per vertex (could be expressed as an index of the chunk)
-
-            layout (location = 1) in vec3 ofs; // per instance
-
-            layout(set = 0, binding = 0) uniform Camera {
-                mat4 view;
-                mat4 proj;
-            };
-
-            layout(set = 1, binding = 0) uniform Model {
-                mat4 model;
-            };
-
-            layout(location = 0) out vec4 o_pos;
+        path: "shaders/chunk.vert",
+    }
+}
 
-            void main() {
-                gl_Position = proj * view * model * vec4(pos + ofs, 1.0);
+pub mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/chunk.frag",
+    }
+}
 
-                o_pos = vec4(pos, 1.0);
-            }
-        ",
+/* shaders for regular (PosVertex) meshes */
+pub mod mesh_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/mesh.vert",
     }
 }
 
-pub mod fs {
+pub mod mesh_fs {
     vulkano_shaders::shader! {
         ty: "fragment",
-        src: r"
-            #version 460
+        path: "shaders/mesh.frag",
+    }
+}
 
-            layout(location = 0) out vec4 f_color;
+/* debug lines (chunk bounds), see debug::DebugView */
+pub mod line_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/line.vert",
+    }
+}
 
-            layout(location = 0) in vec4 i_pos;
+pub mod line_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/line.frag",
+    }
+}
 
-            void main() {
-                float r = 0.5 + 0.5 * sin(i_pos.x * 0.2 + i_pos.y * 0.2);
-                float g = 0.5 + 0.5 * cos(i_pos.y * 0.2 + i_pos.z * 0.2);
-                float b = 0.5 + 0.5 * sin(i_pos.z * 0.2 + i_pos.x * 0.2);
+/* prototype meshes drawn with per instance position, scale and color, see prototypes */
+pub mod instanced_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/instanced.vert",
+    }
+}
 
-                f_color = vec4(r, g, b, 1.0);
-            }
-        ",
+pub mod instanced_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/instanced.frag",
     }
-}
\ No newline at end of file
+}
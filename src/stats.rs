@@ -0,0 +1,153 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy_ecs::{system::Resource, world::World};
+use imgui::Ui;
+
+use crate::{debug::DebugView, prototypes::Prototypes, renderer::Renderer, schematic::Clipboard, world::ChunkWorld};
+
+/// How many frames the frame time graph goes back
+pub const HISTORY: usize = 240;
+
+/// CPU time spent in each part of a frame
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StageTimings {
+    pub app_update: Duration,
+    /// Lua callbacks
+    pub scripts: Duration,
+    pub chunk_update: Duration,
+    /// `get_cmd_bufs`
+    pub record: Duration,
+    pub present: Duration,
+    /// headless only, `Offscreen::draw` including the wait for the gpu
+    pub render: Duration,
+}
+
+/// What the last frame drew
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SceneStats {
+    pub chunks_total: usize,
+    pub chunks_meshed: usize,
+    pub chunks_visible: usize,
+    pub draw_calls: usize,
+    pub triangles: usize,
+    /// vertex, instance and indirect buffers of the chunk meshes, plus the shared index buffer
+    pub chunk_mesh_bytes: u64,
+    /// requests waiting for the chunk builder
+    pub builder_commands: usize,
+    /// built chunks waiting to be picked up by `ChunkWorld::update`
+    pub builder_results: usize,
+}
+
+/*
+Everything the performance panel shows. It lives in the world so benchmarks
+and the headless mode can read the same numbers without going through imgui.
+*/
+#[derive(Resource, Default)]
+pub struct FrameStats {
+    /// milliseconds, oldest first
+    pub frame_times: VecDeque<f32>,
+    /// over every frame, not just the history
+    pub frame_count: usize,
+    pub total_ms: f64,
+    pub worst_ms: f32,
+    pub stages: StageTimings,
+    pub scene: SceneStats,
+}
+
+impl FrameStats {
+    pub fn push_frame(&mut self, dt: Duration) {
+        if self.frame_times.len() == HISTORY {
+            self.frame_times.pop_front();
+        }
+        let ms = dt.as_secs_f32() * 1000.0;
+        self.frame_times.push_back(ms);
+
+        self.frame_count += 1;
+        self.total_ms += ms as f64;
+        self.worst_ms = self.worst_ms.max(ms);
+    }
+
+    pub fn average_ms(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+
+        self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+    }
+
+    pub fn max_ms(&self) -> f32 {
+        self.frame_times.iter().copied().fold(0.0, f32::max)
+    }
+
+    pub fn ui(&self, ui: &Ui) {
+        let times: Vec<f32> = self.frame_times.iter().copied().collect();
+        let average = self.average_ms();
+
+        ui.window("performance").build(|| {
+            ui.text(format!("{:.2} ms ({:.0} fps), worst {:.2} ms", average, 1000.0 / average.max(0.001), self.max_ms()));
+            ui.plot_lines("frame time", &times)
+                .scale_min(0.0)
+                .graph_size([0.0, 60.0])
+                .build();
+
+            ui.separator();
+            let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+            ui.text(format!("app update:   {:.3} ms", ms(self.stages.app_update)));
+            ui.text(format!("scripts:      {:.3} ms", ms(self.stages.scripts)));
+            ui.text(format!("chunk update: {:.3} ms", ms(self.stages.chunk_update)));
+            ui.text(format!("recording:    {:.3} ms", ms(self.stages.record)));
+            ui.text(format!("present:      {:.3} ms", ms(self.stages.present)));
+
+            ui.separator();
+            let scene = &self.scene;
+            ui.text(format!("chunks: {} visible / {} meshed / {} loaded", scene.chunks_visible, scene.chunks_meshed, scene.chunks_total));
+            ui.text(format!("draw calls: {}", scene.draw_calls));
+            ui.text(format!("triangles: {}", scene.triangles));
+            ui.text(format!("chunk meshes: {:.1} MiB", scene.chunk_mesh_bytes as f64 / (1024.0 * 1024.0)));
+            ui.text(format!("builder queue: {} requests, {} results", scene.builder_commands, scene.builder_results));
+        });
+    }
+}
+
+/// Counts what `renderer::record_scene` draws with the current state of the world
+pub fn scene_stats(renderer: &Renderer, world: &mut World) -> SceneStats {
+    let debug = *world.resource::<DebugView>();
+    let mut stats = SceneStats::default();
+
+    let chunkworld = world.resource::<ChunkWorld>();
+    stats.chunks_total = chunkworld.chunk_count();
+    (stats.builder_commands, stats.builder_results) = chunkworld.builder_queue();
+    stats.chunk_mesh_bytes = chunkworld.quad_ebo.ebo.as_ref().map_or(0, |ebo| ebo.size());
+
+    for mesh in chunkworld.meshes.values().flatten() {
+        stats.chunks_meshed += 1;
+        stats.chunk_mesh_bytes += mesh.vbo.size() + mesh.ibo.size() + mesh.indb.as_ref().map_or(0, |indb| indb.size());
+
+        if mesh.visible {
+            stats.chunks_visible += 1;
+        }
+        if mesh.visible || debug.show_culled {
+            stats.draw_calls += 1;
+            stats.triangles += mesh.quads() * 2;
+        }
+    }
+
+    let clipboard = world.resource::<Clipboard>();
+    if debug.chunk_bounds && !chunkworld.meshes.is_empty() || renderer.target.is_some() || clipboard.selection().is_some() {
+        stats.draw_calls += 1;
+    }
+
+    for mesh in &renderer.meshes {
+        stats.draw_calls += 1;
+        stats.triangles += mesh.ebo.content.len() as usize / 3 * mesh.ibo.content.len() as usize;
+    }
+
+    for mesh in world.resource::<Prototypes>().meshes.values() {
+        if let Some(instances) = &mesh.instances {
+            stats.draw_calls += 1;
+            stats.triangles += mesh.ebo.len() as usize / 3 * instances.len() as usize;
+        }
+    }
+
+    stats
+}
@@ -0,0 +1,125 @@
+use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex};
+
+/*
+a chunk vertex packed into two u32s. data:
+
+    bits  0..7   x (0..=CHUNK_SIZE)
+    bits  7..14  y
+    bits 14..21  z
+    bits 21..24  face direction (see voxel_gen::gen_mesh_data_culled)
+    bits 24..26  ambient occlusion (0 = fully occluded, 3 = unoccluded)
+    bits 26..32  material (voxel id)
+
+light:
+    bits  0..4   baked voxel light level (see voxel_light)
+
+keep this in sync with the unpacking done in shaders::vs
+*/
+#[derive(BufferContents, Vertex, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct ChunkVertex {
+    #[format(R32_UINT)]
+    pub data: u32,
+    #[format(R32_UINT)]
+    pub light: u32,
+}
+
+const POS_BITS: u32 = 7;
+const POS_MASK: u32 = (1 << POS_BITS) - 1;
+const FACE_SHIFT: u32 = POS_BITS * 3;
+const AO_SHIFT: u32 = FACE_SHIFT + 3;
+const MATERIAL_SHIFT: u32 = AO_SHIFT + 2;
+
+impl ChunkVertex {
+    pub fn pack(pos: [usize; 3], face: usize, ao: u32, material: usize, light: u32) -> Self {
+        let data = (pos[0] as u32 & POS_MASK)
+            | (pos[1] as u32 & POS_MASK) << POS_BITS
+            | (pos[2] as u32 & POS_MASK) << (POS_BITS * 2)
+            | (face as u32 & 0b111) << FACE_SHIFT
+            | (ao & 0b11) << AO_SHIFT
+            | (material as u32 & 0b111111) << MATERIAL_SHIFT;
+
+        Self { 
+            data, 
+            light: light & 0b1111,
+        }
+    }
+
+    pub fn pos(&self) -> [u32; 3] {
+        [
+            self.data & POS_MASK,
+            (self.data >> POS_BITS) & POS_MASK,
+            (self.data >> (POS_BITS * 2)) & POS_MASK,
+        ]
+    }
+
+    pub fn face(&self) -> u32 {
+        (self.data >> FACE_SHIFT) & 0b111
+    }
+
+    pub fn ao(&self) -> u32 {
+        (self.data >> AO_SHIFT) & 0b11
+    }
+
+    pub fn material(&self) -> u32 {
+        self.data >> MATERIAL_SHIFT
+    }
+
+    pub fn light(&self) -> u32 {
+        self.light
+    }
+}
+
+/// Vertex of the debug lines, drawn as a line list
+#[derive(BufferContents, Vertex, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct LineVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub pos: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
+    pub color: [f32; 3],
+}
+
+/// Per instance data of the instanced pipeline, see `prototypes`. Only an
+/// offset and a uniform scale, instances can't be rotated
+#[derive(BufferContents, Vertex, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct PrototypeInstance {
+    #[format(R32G32B32_SFLOAT)]
+    pub ofs: [f32; 3],
+    #[format(R32_SFLOAT)]
+    pub scale: f32,
+    #[format(R32G32B32_SFLOAT)]
+    pub color: [f32; 3],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkVertex, POS_MASK};
+
+    fn unpack(vertex: ChunkVertex) -> ([u32; 3], u32, u32, u32, u32) {
+        (vertex.pos(), vertex.face(), vertex.ao(), vertex.material(), vertex.light())
+    }
+
+    #[test]
+    fn fields_at_their_maximum_stay_in_their_bits() {
+        let max = POS_MASK as usize;
+
+        assert_eq!(unpack(ChunkVertex::pack([max; 3], 7, 3, 63, 15)), ([POS_MASK; 3], 7, 3, 63, 15));
+
+        /* one field at a time, a field spilling over shows up in its neighbour */
+        assert_eq!(unpack(ChunkVertex::pack([max, 0, 0], 0, 0, 0, 0)), ([POS_MASK, 0, 0], 0, 0, 0, 0));
+        assert_eq!(unpack(ChunkVertex::pack([0, max, 0], 0, 0, 0, 0)), ([0, POS_MASK, 0], 0, 0, 0, 0));
+        assert_eq!(unpack(ChunkVertex::pack([0, 0, max], 0, 0, 0, 0)), ([0, 0, POS_MASK], 0, 0, 0, 0));
+        assert_eq!(unpack(ChunkVertex::pack([0; 3], 7, 0, 0, 0)), ([0; 3], 7, 0, 0, 0));
+        assert_eq!(unpack(ChunkVertex::pack([0; 3], 0, 3, 0, 0)), ([0; 3], 0, 3, 0, 0));
+        assert_eq!(unpack(ChunkVertex::pack([0; 3], 0, 0, 63, 0)), ([0; 3], 0, 0, 63, 0));
+        assert_eq!(unpack(ChunkVertex::pack([0; 3], 0, 0, 0, 15)), ([0; 3], 0, 0, 0, 15));
+    }
+
+    #[test]
+    fn fields_too_big_are_cut_instead_of_spilling_over() {
+        assert_eq!(unpack(ChunkVertex::pack([0; 3], 0, 4, 64, 16)), ([0; 3], 0, 0, 0, 0));
+        assert_eq!(unpack(ChunkVertex::pack([POS_MASK as usize + 1; 3], 8, 0, 0, 0)), ([0; 3], 0, 0, 0, 0));
+    }
+}
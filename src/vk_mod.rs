@@ -1,26 +1,34 @@
+use chaos_vk::graphics::buffer::VkIterBuffer;
+use chaos_vk::graphics::command::{CommandBufferType, SecBuilderType, VkBuilder};
+use chaos_vk::graphics::mesh::mesh::Mesh;
+use chaos_vk::graphics::vertex::{InstanceData, PosVertex};
 use chaos_vk::graphics::vk::{MemAllocators, Vk};
+use glam::{Quat, Vec3};
 use std::sync::Arc;
 
-use vulkano::command_buffer::allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo};
-use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use bevy_ecs::system::Resource;
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferExecFuture, CommandBufferInheritanceInfo, CommandBufferUsage};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
 use vulkano::device::{Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags};
-use vulkano::image::ImageUsage;
-use vulkano::memory::allocator::StandardMemoryAllocator;
-use vulkano::swapchain::{Surface, Swapchain, SwapchainCreateInfo};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline};
+use vulkano::swapchain::Surface;
+use vulkano::sync::future::{FenceSignalFuture, NowFuture};
+use vulkano::sync::{self, GpuFuture};
 use vulkano::VulkanLibrary;
 use vulkano::instance::{Instance, InstanceCreateInfo};
 use winit::event_loop::EventLoop;
-use winit::window::{Window, WindowBuilder};
+use winit::window::WindowBuilder;
 
 pub trait CustomNew {
     fn custom_new(el: &EventLoop<()>) -> Arc<Self>;
-} 
+}
 
 impl CustomNew for Vk {
-    fn custom_new(el: &EventLoop<()>) -> Arc<Self> {     
+    fn custom_new(el: &EventLoop<()>) -> Arc<Self> {
         let library = VulkanLibrary::new().expect("no local Vulkan library/DLL");
-        
+
         let required_extensions = Surface::required_extensions(el);
         let instance = Instance::new(
             library,
@@ -40,58 +48,13 @@ impl CustomNew for Vk {
             ..DeviceExtensions::empty()
         };
 
-        /* properly select a physical device */
-        let (physical_device, queue_family_index) = instance
-            .enumerate_physical_devices()
-            .expect("could not enumerate devices")
-            .filter(|p| p.supported_extensions().contains(&device_extensions))
-            .filter_map(|p| {
-                p.queue_family_properties()
-                    .iter()
-                    .enumerate()
-                    .position(|(i, q)| {
-                        q.queue_flags.contains(QueueFlags::GRAPHICS)
-                            && p.surface_support(i as u32, &surface).unwrap_or(false)
-                    })
-                    .map(|q| (p, q as u32))
-            })
-            .min_by_key(|(p, _)| match p.properties().device_type {
-                PhysicalDeviceType::DiscreteGpu => 0,
-                PhysicalDeviceType::IntegratedGpu => 1,
-                PhysicalDeviceType::VirtualGpu => 2,
-                PhysicalDeviceType::Cpu => 3,
-
-                _ => 4,
-            })
-            .expect("no device available");
-
-    
-        // for family in physical_device.queue_family_properties() {
-        //     println!("Found a queue family with {:?} queue(s)", family.queue_count);
-        // }
-        
-        let (device, mut queues) = Device::new(
-                physical_device.clone(),
-                DeviceCreateInfo {
-                    queue_create_infos: vec![QueueCreateInfo {
-                        queue_family_index,
-                        ..Default::default()
-                    }],
-                    enabled_features: Features {
-                        multi_draw_indirect: true,
-                        ..Default::default()
-                    },
-                    enabled_extensions: device_extensions,
-                    ..Default::default()
-                },
-            )
-            .expect("failed to create device");
-
+        let (physical_device, queue_family_index) = pick_device(&instance, &device_extensions, |p, i| {
+            p.surface_support(i, &surface).unwrap_or(false)
+        });
+        let (device, queue) = create_device(physical_device.clone(), queue_family_index, device_extensions);
         let allocators = MemAllocators::new(device.clone());
-    
-        let queue = queues.next().unwrap();
 
-        Arc::new(Self {
+        Arc::new(Vk {
             queue,
             physical_device,
             device,
@@ -102,4 +65,186 @@ impl CustomNew for Vk {
             window,
         })
     }
-}
\ No newline at end of file
+}
+
+/*
+Everything drawing the scene needs, without the window and surface `Vk` carries,
+so it can be created without a display server (lavapipe on CI for instance).
+The windowed app gets it from its `Vk` with `Gpu::from_vk`, `run_headless`
+with `Gpu::headless`.
+
+chaos_vk's helpers all take a `Vk`, the ones the scene uses are redone here.
+*/
+pub struct Gpu {
+    pub instance: Arc<Instance>,
+    pub physical_device: Arc<PhysicalDevice>,
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    pub queue_family_index: u32,
+    pub allocators: Arc<MemAllocators>,
+}
+
+impl Gpu {
+    pub fn from_vk(vk: &Vk) -> Arc<Self> {
+        Arc::new(Self {
+            instance: vk.instance.clone(),
+            physical_device: vk.physical_device.clone(),
+            device: vk.device.clone(),
+            queue: vk.queue.clone(),
+            queue_family_index: vk.queue_family_index,
+            allocators: vk.allocators.clone(),
+        })
+    }
+
+    /// No instance extensions, no swapchain and no surface: the device only
+    /// has to draw, so software drivers like lavapipe can be picked
+    pub fn headless() -> Arc<Self> {
+        let library = VulkanLibrary::new().expect("no local Vulkan library/DLL");
+        let instance = Instance::new(library, InstanceCreateInfo::default())
+            .expect("failed to create instance");
+
+        let device_extensions = DeviceExtensions::empty();
+        let (physical_device, queue_family_index) = pick_device(&instance, &device_extensions, |_, _| true);
+        let (device, queue) = create_device(physical_device.clone(), queue_family_index, device_extensions);
+
+        Arc::new(Self {
+            instance,
+            physical_device,
+            allocators: Arc::new(MemAllocators::new(device.clone())),
+            device,
+            queue,
+            queue_family_index,
+        })
+    }
+
+    /// Same as `VkBuilder::new_once`
+    pub fn builder_once(&self) -> VkBuilder {
+        self.primary(CommandBufferUsage::OneTimeSubmit)
+    }
+
+    /// Same as `VkBuilder::new_multiple`
+    pub fn builder_multiple(&self) -> VkBuilder {
+        self.primary(CommandBufferUsage::MultipleSubmit)
+    }
+
+    fn primary(&self, usage: CommandBufferUsage) -> VkBuilder {
+        VkBuilder(
+            AutoCommandBufferBuilder::primary(&self.allocators.command, self.queue_family_index, usage)
+                .unwrap()
+        )
+    }
+
+    /// Same as `VkBuilder::new_secondary`
+    pub fn builder_secondary(&self, inheritance_info: CommandBufferInheritanceInfo) -> SecBuilderType {
+        AutoCommandBufferBuilder::secondary(
+            &self.allocators.command,
+            self.queue_family_index,
+            CommandBufferUsage::SimultaneousUse,
+            inheritance_info,
+        )
+        .unwrap()
+    }
+
+    /// Same as `command::submit_cmd_buf`
+    pub fn submit(&self, cmd_buf: CommandBufferType) -> FenceSignalFuture<CommandBufferExecFuture<NowFuture>> {
+        sync::now(self.device.clone())
+            .then_execute(self.queue.clone(), cmd_buf)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+    }
+
+    /// Same as `utils::descriptor_set`, without the index it returns
+    pub fn descriptor_set(
+        &self,
+        set: usize,
+        pipeline: Arc<GraphicsPipeline>,
+        writes: impl IntoIterator<Item = WriteDescriptorSet>,
+    ) -> Arc<PersistentDescriptorSet> {
+        let layout = pipeline.layout().set_layouts()[set].clone();
+
+        PersistentDescriptorSet::new(&self.allocators.descriptor_set, layout, writes, [])
+            .unwrap()
+    }
+
+    /// Same as `Mesh::new`
+    pub fn mesh(&self, vertices: &Vec<PosVertex>, indices: &Vec<u32>) -> Mesh {
+        let instances = vec![InstanceData {ofs: [0.0, 0.0, 0.0]}];
+
+        Mesh {
+            vertices: vertices.to_vec(),
+            indices: indices.to_vec(),
+            instances: instances.to_vec(),
+
+            position: Vec3::ZERO,
+            rotation: Quat::default(),
+            scale: Vec3::ONE,
+            color: Vec3::ONE,
+
+            vbo: VkIterBuffer::vertex(self.allocators.clone(), vertices.to_vec()),
+            ebo: VkIterBuffer::index(self.allocators.clone(), indices.to_vec()),
+            ibo: VkIterBuffer::vertex(self.allocators.clone(), instances),
+        }
+    }
+}
+
+/// Lets systems upload things to the gpu
+#[derive(Resource, Clone)]
+pub struct GpuContext(pub Arc<Gpu>);
+
+/// The best device with a graphics queue family `queue_ok` accepts
+fn pick_device(
+    instance: &Arc<Instance>,
+    device_extensions: &DeviceExtensions,
+    queue_ok: impl Fn(&PhysicalDevice, u32) -> bool,
+) -> (Arc<PhysicalDevice>, u32) {
+    instance
+        .enumerate_physical_devices()
+        .expect("could not enumerate devices")
+        .filter(|p| p.supported_extensions().contains(device_extensions))
+        .filter_map(|p| {
+            p.queue_family_properties()
+                .iter()
+                .enumerate()
+                .position(|(i, q)| {
+                    q.queue_flags.contains(QueueFlags::GRAPHICS) && queue_ok(&p, i as u32)
+                })
+                .map(|q| (p, q as u32))
+        })
+        .min_by_key(|(p, _)| match p.properties().device_type {
+            PhysicalDeviceType::DiscreteGpu => 0,
+            PhysicalDeviceType::IntegratedGpu => 1,
+            PhysicalDeviceType::VirtualGpu => 2,
+            PhysicalDeviceType::Cpu => 3,
+
+            _ => 4,
+        })
+        .expect("no device available")
+}
+
+fn create_device(
+    physical_device: Arc<PhysicalDevice>,
+    queue_family_index: u32,
+    device_extensions: DeviceExtensions,
+) -> (Arc<Device>, Arc<Queue>) {
+    let (device, mut queues) = Device::new(
+            physical_device.clone(),
+            DeviceCreateInfo {
+                queue_create_infos: vec![QueueCreateInfo {
+                    queue_family_index,
+                    ..Default::default()
+                }],
+                enabled_features: Features {
+                    multi_draw_indirect: true,
+                    /* wireframe debug view, optional */
+                    fill_mode_non_solid: physical_device.supported_features().fill_mode_non_solid,
+                    ..Default::default()
+                },
+                enabled_extensions: device_extensions,
+                ..Default::default()
+            },
+        )
+        .expect("failed to create device");
+
+    (device, queues.next().unwrap())
+}
@@ -0,0 +1,354 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use glam::{ivec3, IVec3};
+
+use crate::world::{Chunk, ChunkKey, CHUNK_SIZE};
+
+/*
+Baked voxel lighting. Light levels go from 0 to MAX_LIGHT and spread through
+air with a BFS, losing one level per voxel. Sources are either emissive
+materials or placed lights (torches), which live on the world rather than on
+the chunks so that they survive chunks being regenerated.
+
+Everything here works on a plain map of chunks, every function reports the
+chunks whose meshes need to be rebuilt through `dirty`.
+*/
+
+pub const MAX_LIGHT: u8 = 15;
+
+pub type Chunks = HashMap<ChunkKey, Chunk>;
+pub type LightSources = HashMap<IVec3, u8>;
+
+const NEIGHBOURS: [IVec3; 6] = [
+    ivec3(-1, 0, 0),
+    ivec3(1, 0, 0),
+    ivec3(0, -1, 0),
+    ivec3(0, 1, 0),
+    ivec3(0, 0, -1),
+    ivec3(0, 0, 1),
+];
+
+/// Light emitted by a material. Material 2 is glowing crystal
+pub fn emission(id: usize) -> u8 {
+    match id {
+        2 => 14,
+        _ => 0,
+    }
+}
+
+pub fn get_light(chunks: &Chunks, pos: IVec3) -> u8 {
+    let (key, idx) = Chunk::locate_voxel(pos);
+    chunks.get(&key).map_or(0, |chunk| chunk.light[idx])
+}
+
+/// Unloaded voxels are considered opaque so light doesn't leak into them
+fn is_transparent(chunks: &Chunks, pos: IVec3) -> bool {
+    let (key, idx) = Chunk::locate_voxel(pos);
+    chunks.get(&key).map_or(false, |chunk| chunk.get(idx).id == 0)
+}
+
+fn source_level(chunks: &Chunks, sources: &LightSources, pos: IVec3) -> u8 {
+    let (key, idx) = Chunk::locate_voxel(pos);
+    let Some(chunk) = chunks.get(&key) else {
+        return 0;
+    };
+
+    let placed = sources.get(&pos).copied().unwrap_or(0);
+    placed.max(emission(chunk.get(idx).id))
+}
+
+fn set_light(chunks: &mut Chunks, pos: IVec3, level: u8, dirty: &mut HashSet<ChunkKey>) {
+    let (key, idx) = Chunk::locate_voxel(pos);
+    if let Some(chunk) = chunks.get_mut(&key) {
+        chunk.light[idx] = level;
+
+        /* faces of the neighbouring chunks can sample this voxel too */
+        dirty.insert(key);
+        for ofs in NEIGHBOURS {
+            dirty.insert(Chunk::locate_voxel(pos + ofs).0);
+        }
+    }
+}
+
+/// Spreads the light of every voxel in `queue` to its neighbours
+pub fn propagate(chunks: &mut Chunks, queue: &mut VecDeque<IVec3>, dirty: &mut HashSet<ChunkKey>) {
+    while let Some(pos) = queue.pop_front() {
+        let level = get_light(chunks, pos);
+        if level <= 1 {
+            continue;
+        }
+
+        for ofs in NEIGHBOURS {
+            let neighbour = pos + ofs;
+
+            if is_transparent(chunks, neighbour) && get_light(chunks, neighbour) + 1 < level {
+                set_light(chunks, neighbour, level - 1, dirty);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+}
+
+pub fn add_source(chunks: &mut Chunks, pos: IVec3, level: u8, dirty: &mut HashSet<ChunkKey>) {
+    let level = level.min(MAX_LIGHT);
+    if level <= get_light(chunks, pos) {
+        return;
+    }
+
+    set_light(chunks, pos, level, dirty);
+    propagate(chunks, &mut VecDeque::from([pos]), dirty);
+}
+
+/// Removes all the light that spread from or through `pos`, then lights the
+/// cleared area again from whatever sources are left around it
+pub fn remove_light(chunks: &mut Chunks, sources: &LightSources, pos: IVec3, dirty: &mut HashSet<ChunkKey>) {
+    let mut removal = VecDeque::from([(pos, get_light(chunks, pos))]);
+    let mut relight = VecDeque::new();
+    set_light(chunks, pos, 0, dirty);
+
+    while let Some((pos, level)) = removal.pop_front() {
+        for ofs in NEIGHBOURS {
+            let neighbour = pos + ofs;
+            let neighbour_level = get_light(chunks, neighbour);
+
+            if neighbour_level == 0 {
+                continue;
+            }
+
+            if neighbour_level < level {
+                set_light(chunks, neighbour, 0, dirty);
+                removal.push_back((neighbour, neighbour_level));
+            } else {
+                relight.push_back(neighbour);
+            }
+        }
+
+        let source = source_level(chunks, sources, pos);
+        if source > 0 {
+            set_light(chunks, pos, source, dirty);
+            relight.push_back(pos);
+        }
+    }
+
+    propagate(chunks, &mut relight, dirty);
+}
+
+/// Has to be called after the voxel at `pos` changed
+pub fn on_voxel_changed(chunks: &mut Chunks, sources: &LightSources, pos: IVec3, dirty: &mut HashSet<ChunkKey>) {
+    remove_light(chunks, sources, pos, dirty);
+
+    let mut queue = VecDeque::new();
+    if is_transparent(chunks, pos) {
+        /* let the light around flow into the voxel */
+        queue.extend(NEIGHBOURS.iter().map(|ofs| pos + *ofs));
+    }
+
+    let source = source_level(chunks, sources, pos);
+    if source > get_light(chunks, pos) {
+        set_light(chunks, pos, source, dirty);
+        queue.push_back(pos);
+    }
+
+    propagate(chunks, &mut queue, dirty);
+}
+
+/// Lights a freshly loaded chunk from its own sources and from the light
+/// already present on the borders of its neighbours
+pub fn seed_chunk(chunks: &mut Chunks, sources: &LightSources, key: ChunkKey, dirty: &mut HashSet<ChunkKey>) {
+    let Some(chunk) = chunks.get(&key) else {
+        return;
+    };
+
+    let origin = Chunk::get_worldpos(&key).as_ivec3();
+    let mut seeds = vec![];
+
+    for idx in chunk.emitters() {
+        seeds.push((origin + Chunk::local_pos(idx), emission(chunk.get(idx).id)));
+    }
+
+    for (pos, level) in sources {
+        if Chunk::locate_voxel(*pos).0 == key {
+            seeds.push((*pos, *level));
+        }
+    }
+
+    let mut queue = VecDeque::new();
+    for (pos, level) in seeds {
+        if level > get_light(chunks, pos) {
+            set_light(chunks, pos, level, dirty);
+        }
+        queue.push_back(pos);
+    }
+
+    /* the layer of voxels right outside of each face of the chunk */
+    let size = CHUNK_SIZE as i32;
+    for a in 0..size {
+        for b in 0..size {
+            for c in [-1, size] {
+                for pos in [ivec3(c, a, b), ivec3(a, c, b), ivec3(a, b, c)] {
+                    let pos = origin + pos;
+                    if get_light(chunks, pos) > 1 {
+                        queue.push_back(pos);
+                    }
+                }
+            }
+        }
+    }
+
+    propagate(chunks, &mut queue, dirty);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashSet, VecDeque};
+
+    use glam::{ivec3, IVec3};
+
+    use super::*;
+    use crate::world::{Voxel, CHUNK_VOLUME};
+
+    fn air(keys: &[ChunkKey]) -> Chunks {
+        keys.iter().map(|key| (*key, Chunk::empty(*key))).collect()
+    }
+
+    fn set_voxel(chunks: &mut Chunks, pos: IVec3, id: usize) {
+        let (key, idx) = Chunk::locate_voxel(pos);
+        chunks.get_mut(&key).unwrap().set(idx, Voxel { id });
+    }
+
+    #[test]
+    fn loses_a_level_per_voxel() {
+        let mut chunks = air(&[(0, 0, 0)]);
+        let mut dirty = HashSet::new();
+        add_source(&mut chunks, ivec3(10, 10, 10), MAX_LIGHT, &mut dirty);
+
+        assert_eq!(get_light(&chunks, ivec3(10, 10, 10)), 15);
+        assert_eq!(get_light(&chunks, ivec3(12, 10, 10)), 13);
+        assert_eq!(get_light(&chunks, ivec3(11, 11, 11)), 12);
+        assert_eq!(get_light(&chunks, ivec3(24, 10, 10)), 1);
+        assert_eq!(get_light(&chunks, ivec3(25, 10, 10)), 0);
+        assert!(dirty.contains(&(0, 0, 0)));
+    }
+
+    #[test]
+    fn propagate_spreads_from_every_voxel_queued() {
+        let mut chunks = air(&[(0, 0, 0)]);
+        let mut dirty = HashSet::new();
+        chunks.get_mut(&(0, 0, 0)).unwrap().light[Chunk::local_index(ivec3(5, 5, 5))] = 4;
+        chunks.get_mut(&(0, 0, 0)).unwrap().light[Chunk::local_index(ivec3(20, 5, 5))] = 3;
+
+        propagate(&mut chunks, &mut VecDeque::from([ivec3(5, 5, 5), ivec3(20, 5, 5)]), &mut dirty);
+
+        assert_eq!(get_light(&chunks, ivec3(8, 5, 5)), 1);
+        assert_eq!(get_light(&chunks, ivec3(21, 5, 5)), 2);
+        assert_eq!(get_light(&chunks, ivec3(13, 5, 5)), 0);
+    }
+
+    #[test]
+    fn crosses_into_the_neighbouring_chunk() {
+        let mut chunks = air(&[(0, 0, 0), (1, 0, 0)]);
+        let mut dirty = HashSet::new();
+        add_source(&mut chunks, ivec3(62, 5, 5), 10, &mut dirty);
+
+        assert_eq!(get_light(&chunks, ivec3(63, 5, 5)), 9);
+        assert_eq!(get_light(&chunks, ivec3(64, 5, 5)), 8);
+        assert_eq!(get_light(&chunks, ivec3(70, 5, 5)), 2);
+        assert!(dirty.contains(&(1, 0, 0)));
+    }
+
+    #[test]
+    fn unloaded_chunks_stay_dark() {
+        let mut chunks = air(&[(0, 0, 0)]);
+        let mut dirty = HashSet::new();
+        add_source(&mut chunks, ivec3(63, 5, 5), 10, &mut dirty);
+
+        assert_eq!(get_light(&chunks, ivec3(64, 5, 5)), 0);
+        assert_eq!(get_light(&chunks, ivec3(62, 5, 5)), 9);
+    }
+
+    #[test]
+    fn removing_a_source_clears_its_light() {
+        let mut chunks = air(&[(0, 0, 0), (1, 0, 0)]);
+        let mut sources = LightSources::new();
+        let mut dirty = HashSet::new();
+        let (a, b) = (ivec3(60, 5, 5), ivec3(50, 5, 5));
+
+        sources.insert(a, 12);
+        sources.insert(b, 6);
+        add_source(&mut chunks, a, 12, &mut dirty);
+        add_source(&mut chunks, b, 6, &mut dirty);
+
+        sources.remove(&a);
+        remove_light(&mut chunks, &sources, a, &mut dirty);
+
+        assert_eq!(get_light(&chunks, a), 0);
+        assert_eq!(get_light(&chunks, ivec3(66, 5, 5)), 0);
+        /* what the other source lights is lit again */
+        assert_eq!(get_light(&chunks, b), 6);
+        assert_eq!(get_light(&chunks, ivec3(53, 5, 5)), 3);
+    }
+
+    #[test]
+    fn walls_block_and_reopen() {
+        let mut chunks = air(&[(0, 0, 0)]);
+        let sources = LightSources::new();
+        let mut dirty = HashSet::new();
+        add_source(&mut chunks, ivec3(10, 10, 10), MAX_LIGHT, &mut dirty);
+
+        set_voxel(&mut chunks, ivec3(11, 10, 10), 1);
+        on_voxel_changed(&mut chunks, &sources, ivec3(11, 10, 10), &mut dirty);
+        assert_eq!(get_light(&chunks, ivec3(11, 10, 10)), 0);
+        /* around the wall */
+        assert_eq!(get_light(&chunks, ivec3(12, 10, 10)), 11);
+
+        set_voxel(&mut chunks, ivec3(11, 10, 10), 0);
+        on_voxel_changed(&mut chunks, &sources, ivec3(11, 10, 10), &mut dirty);
+        assert_eq!(get_light(&chunks, ivec3(11, 10, 10)), 14);
+        assert_eq!(get_light(&chunks, ivec3(12, 10, 10)), 13);
+    }
+
+    #[test]
+    fn placing_crystal_lights_it_up() {
+        let mut chunks = air(&[(0, 0, 0)]);
+        let sources = LightSources::new();
+        let mut dirty = HashSet::new();
+
+        set_voxel(&mut chunks, ivec3(5, 5, 5), 2);
+        on_voxel_changed(&mut chunks, &sources, ivec3(5, 5, 5), &mut dirty);
+
+        assert_eq!(get_light(&chunks, ivec3(5, 5, 5)), emission(2));
+        assert_eq!(get_light(&chunks, ivec3(5, 7, 5)), emission(2) - 2);
+    }
+
+    #[test]
+    fn seeds_a_chunk_from_its_crystals_and_placed_lights() {
+        let mut voxels = vec![Voxel { id: 0 }; CHUNK_VOLUME];
+        voxels[Chunk::local_index(ivec3(3, 3, 3))] = Voxel { id: 2 };
+        let mut chunks = Chunks::new();
+        chunks.insert((0, 0, 0), Chunk::from_voxels((0, 0, 0), voxels));
+
+        let sources = LightSources::from([(ivec3(40, 40, 40), 9)]);
+        let mut dirty = HashSet::new();
+        seed_chunk(&mut chunks, &sources, (0, 0, 0), &mut dirty);
+
+        assert_eq!(get_light(&chunks, ivec3(3, 3, 3)), 14);
+        assert_eq!(get_light(&chunks, ivec3(3, 3, 6)), 11);
+        assert_eq!(get_light(&chunks, ivec3(40, 40, 40)), 9);
+        assert_eq!(get_light(&chunks, ivec3(40, 41, 40)), 8);
+    }
+
+    #[test]
+    fn seeds_a_chunk_from_the_light_of_its_neighbours() {
+        let mut chunks = air(&[(0, 0, 0)]);
+        let sources = LightSources::new();
+        let mut dirty = HashSet::new();
+        add_source(&mut chunks, ivec3(63, 5, 5), 10, &mut dirty);
+
+        chunks.insert((1, 0, 0), Chunk::empty((1, 0, 0)));
+        seed_chunk(&mut chunks, &sources, (1, 0, 0), &mut dirty);
+
+        assert_eq!(get_light(&chunks, ivec3(64, 5, 5)), 9);
+        assert_eq!(get_light(&chunks, ivec3(66, 5, 5)), 7);
+        assert!(dirty.contains(&(1, 0, 0)));
+    }
+}
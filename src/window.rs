@@ -0,0 +1,111 @@
+use bevy_ecs::system::Resource;
+use glam::Mat4;
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport};
+use winit::{dpi::PhysicalSize, window::{Fullscreen, Window}};
+
+/// Vertical field of view a window starts with, in degrees
+pub const FOV_Y: f32 = 80.0;
+pub const Z_NEAR: f32 = 0.1;
+pub const Z_FAR: f32 = 1000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowMode {
+    Windowed,
+    /// fullscreen window on the current monitor, keeps the desktop resolution
+    Borderless,
+    /// takes over the monitor with its best video mode
+    Fullscreen,
+}
+
+/*
+The one place the size of the window is read from. The camera projection,
+the frustum culling and the viewport all go through this, so they can't
+disagree after a resize.
+*/
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct WindowState {
+    pub width: u32,
+    pub height: u32,
+    pub mode: WindowMode,
+    /// vertical, in degrees
+    pub fov_y: f32,
+}
+
+impl WindowState {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            mode: WindowMode::Windowed,
+            fov_y: FOV_Y,
+        }
+    }
+
+    /// Minimized windows report a size of 0, keep the last usable one
+    pub fn resize(&mut self, size: PhysicalSize<u32>) -> bool {
+        if size.width == 0 || size.height == 0 {
+            return false;
+        }
+
+        let changed = self.width != size.width || self.height != size.height;
+        self.width = size.width;
+        self.height = size.height;
+
+        changed
+    }
+
+    pub fn extent(&self) -> [u32; 2] {
+        [self.width, self.height]
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    pub fn projection(&self) -> Mat4 {
+        Mat4::perspective_rh(self.fov_y.to_radians(), self.aspect(), Z_NEAR, Z_FAR)
+    }
+
+    pub fn set_mode(&mut self, window: &Window, mode: WindowMode) {
+        self.mode = mode;
+
+        let fullscreen = match mode {
+            WindowMode::Windowed => None,
+            WindowMode::Borderless => Some(Fullscreen::Borderless(window.current_monitor())),
+            WindowMode::Fullscreen => window
+                .current_monitor()
+                .and_then(|monitor| monitor.video_modes().max_by_key(|mode| (mode.size().width * mode.size().height, mode.refresh_rate_millihertz())))
+                .map(Fullscreen::Exclusive)
+                /* not every platform has video modes */
+                .or(Some(Fullscreen::Borderless(None))),
+        };
+
+        window.set_fullscreen(fullscreen);
+    }
+
+    /// F11 goes back and forth between windowed and borderless
+    pub fn toggle_fullscreen(&mut self, window: &Window) {
+        let mode = match self.mode {
+            WindowMode::Windowed => WindowMode::Borderless,
+            _ => WindowMode::Windowed,
+        };
+
+        self.set_mode(window, mode);
+    }
+}
+
+/// Viewport covering a target of `extent`, set as dynamic state before drawing
+pub fn viewport(extent: [u32; 2]) -> Viewport {
+    Viewport {
+        offset: [0.0, 0.0],
+        extent: [extent[0] as f32, extent[1] as f32],
+        depth_range: 0.0..=1.0,
+    }
+}
+
+pub fn scissor(extent: [u32; 2]) -> Scissor {
+    Scissor {
+        offset: [0, 0],
+        extent,
+    }
+}
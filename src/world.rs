@@ -1,16 +1,17 @@
 use core::f32;
-use std::{collections::{HashMap, VecDeque}, sync::Arc};
+use std::{collections::{HashMap, HashSet, VecDeque}, fs::File, io::{self, BufReader, BufWriter, Read, Write}, path::Path, sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::Duration};
 
 use bevy_ecs::system::{Commands, Resource};
 use chaos_vk::graphics::{buffer::VkIterBuffer, camera::Camera, mesh::mesh::Mesh, vertex::InstanceData, vk::{MemAllocators, Vk}};
-use glam::{quat, vec3, Vec3};
+use glam::{ivec3, quat, vec3, IVec3, Vec3};
 use noise::{NoiseFn, Perlin, PerlinSurflet};
 use tokio::{sync::{mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender}, Mutex}, task::JoinHandle};
 use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}};
 
-use crate::{chunk_builder::{get_lod_by_distance, ChunkBuilder, ChunkBuilderChannelData, ChunkBuilderCommands}, chunkmesh::ChunkMesh, culler::ChunkCuller, geometry::voxel_gen, math::{rand_betw, rand_vec3}};
+use crate::{chunk_builder::{get_lod_by_distance, ChunkBuilder, ChunkBuilderChannelData, ChunkBuilderCommands}, chunkmesh::{ChunkMesh, QuadIndexBuffer}, culler::ChunkCuller, geometry::voxel_gen, history::{self, EditHistory}, math::{rand_betw, rand_vec3}, raycast::{self, RayHit}, voxel_light::{self, LightSources}, window::WindowState};
 
 pub const CHUNK_SIZE: usize = 64;
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
 pub const DRAW_DISTANCE: f32 = 400.0;
 
 pub type ChunkKey = (isize, isize, isize);
@@ -24,6 +25,11 @@ pub struct Voxel {
 pub struct Chunk {
     key: ChunkKey,
     voxels: Vec<Voxel>,
+    /// baked light level of every voxel, see `voxel_light`
+    pub light: Vec<u8>,
+    /// voxels that may glow, found when the chunk gets built so that lighting
+    /// it on the main thread doesn't have to look through all of them
+    emitters: Vec<usize>,
     pub lod: usize,
     pub outdated: bool,
 }
@@ -55,24 +61,96 @@ impl Chunk {
             }
         }
     
+        Self::from_voxels(key, voxels)
+    }
+
+    pub fn from_voxels(key: ChunkKey, voxels: Vec<Voxel>) -> Self {
+        let emitters = (0..voxels.len())
+            .filter(|idx| voxel_light::emission(voxels[*idx].id) > 0)
+            .collect();
+
         Self {
             key,
             voxels,
+            light: vec![0; CHUNK_VOLUME],
+            emitters,
             lod: 0,
             outdated: false,
         }
     }
-    
-    
 
-    pub fn get_mesh(&mut self, allocators: Arc<MemAllocators>) -> Option<ChunkMesh> {
-        let voxels = self.voxels.clone();
+    /// A chunk made only of air
+    pub fn empty(key: ChunkKey) -> Self {
+        Self::from_voxels(key, vec![Voxel { id: 0 }; CHUNK_VOLUME])
+    }
+
+    pub fn get(&self, idx: usize) -> Voxel {
+        self.voxels[idx]
+    }
+
+    pub fn set(&mut self, idx: usize, voxel: Voxel) {
+        self.voxels[idx] = voxel;
+        if voxel_light::emission(voxel.id) > 0 && !self.emitters.contains(&idx) {
+            self.emitters.push(idx);
+        }
+    }
+
+    /// Indices of the voxels that glow, checked again since voxels that were
+    /// changed stay on the list
+    pub fn emitters(&self) -> impl Iterator<Item = usize> + '_ {
+        self.emitters.iter().copied().filter(|idx| voxel_light::emission(self.voxels[*idx].id) > 0)
+    }
+
+    /// Light at a position relative to the chunk, zero outside of it
+    pub fn local_light(&self, x: isize, y: isize, z: isize) -> u8 {
+        let size = CHUNK_SIZE as isize;
+        if x < 0 || x >= size || y < 0 || y >= size || z < 0 || z >= size {
+            return 0;
+        }
+
+        self.light[Self::local_index(ivec3(x as i32, y as i32, z as i32))]
+    }
+
+    pub fn local_index(pos: IVec3) -> usize {
+        pos.x as usize * CHUNK_SIZE * CHUNK_SIZE + pos.y as usize * CHUNK_SIZE + pos.z as usize
+    }
+
+    pub fn local_pos(idx: usize) -> IVec3 {
+        let (x, y, z) = voxel_gen::get_pos(idx);
+        ivec3(x as i32, y as i32, z as i32)
+    }
+
+    /// The chunk a voxel in world space belongs to and its index inside of it
+    pub fn locate_voxel(pos: IVec3) -> (ChunkKey, usize) {
+        let size = CHUNK_SIZE as i32;
+        let key = (
+            pos.x.div_euclid(size) as isize, 
+            pos.y.div_euclid(size) as isize, 
+            pos.z.div_euclid(size) as isize,
+        );
+        let local = ivec3(pos.x.rem_euclid(size), pos.y.rem_euclid(size), pos.z.rem_euclid(size));
+
+        (key, Self::local_index(local))
+    }
+
+    pub fn get_mesh(&self, allocators: Arc<MemAllocators>) -> Option<ChunkMesh> {
+        self.get_mesh_with_light(allocators, &|x, y, z| self.local_light(x, y, z))
+    }
+
+    /// Same as `get_mesh`, but lets the light be sampled past the chunk borders
+    pub fn get_mesh_with_light(
+        &self, 
+        allocators: Arc<MemAllocators>, 
+        light: &impl Fn(isize, isize, isize) -> u8,
+    ) -> Option<ChunkMesh> {
+        let voxels = &self.voxels;
         let key = self.key;
 
-        let (vertices, indices) = voxel_gen::gen_mesh_data_culled(&voxels, self.lod + 1);
+        let vertices = voxel_gen::gen_mesh_data_culled(&voxels, self.lod + 1, light);
 
-        if indices.len() > 0 {
-            let mut mesh = ChunkMesh::new(allocators.clone(), &vertices, &indices);
+        if vertices.len() > 0 {
+            let mut mesh = ChunkMesh::new(allocators.clone(), &vertices);
+            mesh.lod = self.lod;
     
             let pos = Self::get_worldpos(&key);
             
@@ -99,16 +177,13 @@ impl Chunk {
         }
     }
 
-    /* this might be broken? */
+    /// The chunk containing a point in world space, which for negative
+    /// coordinates is the one further from zero: -0.5 is in chunk -1
     pub fn get_ijk_chunkspace(pos: Vec3) -> ChunkKey {
-        let i = (pos.x / CHUNK_SIZE as f32).floor() as isize;
-        let j = (pos.y / CHUNK_SIZE as f32).floor() as isize;
-        let k = (pos.z / CHUNK_SIZE as f32).floor() as isize;
-
-        (i, j, k)
+        Self::locate_voxel(pos.floor().as_ivec3()).0
     }
 
-    /* this too, might be broken */
+    /// Corner of the chunk with the lowest coordinates
     pub fn get_worldpos(key: &ChunkKey) -> Vec3 {
         let x = (key.0 * CHUNK_SIZE as isize) as f32;
         let y = (key.1 * CHUNK_SIZE as isize) as f32;
@@ -124,34 +199,78 @@ impl Chunk {
     }
 }
 
+/// What happened to the world since the last `take_events`, for scripts
+#[derive(Clone, Debug, Default)]
+pub struct WorldEvents {
+    /// chunks that came in from the builder
+    pub loaded: Vec<ChunkKey>,
+    /// lowest and highest corner of every voxel that changed
+    pub edited: Option<(IVec3, IVec3)>,
+}
+
 #[derive(Resource)]
 pub struct ChunkWorld {
     chunks: HashMap<ChunkKey, Chunk>,
 
     pub meshes: HashMap<ChunkKey, Option<ChunkMesh>>,
+    pub quad_ebo: QuadIndexBuffer,
+    light_sources: LightSources,
+    /// voxels changed by hand, by chunk and index. Chunks come back from the
+    /// builder regenerated from noise, so these get applied on top of them
+    edits: HashMap<ChunkKey, HashMap<usize, Voxel>>,
+    pub history: EditHistory,
+    events: WorldEvents,
+    /// chunks dropped to be built again at another lod
+    rebuilding: HashSet<ChunkKey>,
+    /// chunks whose meshes need to be rebuilt on the main thread
+    dirty: HashSet<ChunkKey>,
     chunks_to_remove: Vec<ChunkKey>,
     meshes_to_remove: Vec<ChunkKey>,
     queue: VecDeque<ChunkKey>,
 
     chunk_builder_tx: Sender<ChunkBuilderCommands>,
     chunk_builder_rx: Receiver<ChunkBuilderChannelData>,
+    /// see `ChunkBuilder::pending`
+    chunk_builder_pending: Arc<AtomicUsize>,
 }
 
 impl ChunkWorld {
-    pub fn new(allocators: Arc<MemAllocators>) -> Self {
-        let chunks = HashMap::new();
-
+    /// `in_order` builds the chunks in a fixed order, see `ChunkBuilder::begin_loop`
+    pub fn new(allocators: Arc<MemAllocators>, in_order: bool) -> Self {
         let mut chunk_builder = ChunkBuilder::new();
-        chunk_builder.begin_loop(allocators);
+        chunk_builder.begin_loop(allocators.clone(), in_order);
+
+        Self::with_builder(chunk_builder, QuadIndexBuffer::new(allocators, 1 << 14))
+    }
+
+    /// No builder tasks and no gpu buffers, the chunks only come from `insert_chunk`
+    #[cfg(test)]
+    pub fn detached() -> Self {
+        Self::with_builder(ChunkBuilder::new(), QuadIndexBuffer::empty())
+    }
 
+    #[cfg(test)]
+    pub fn insert_chunk(&mut self, chunk: Chunk) {
+        self.chunks.insert(chunk.key, chunk);
+    }
+
+    fn with_builder(chunk_builder: ChunkBuilder, quad_ebo: QuadIndexBuffer) -> Self {
         Self {
-            chunks: chunks.clone(),
+            chunks: HashMap::new(),
             meshes: HashMap::new(),
+            quad_ebo,
+            light_sources: HashMap::new(),
+            edits: HashMap::new(),
+            history: EditHistory::new(),
+            events: WorldEvents::default(),
+            rebuilding: HashSet::new(),
+            dirty: HashSet::new(),
             chunks_to_remove: vec![],
             meshes_to_remove: vec![],
             queue: VecDeque::new(),
             chunk_builder_tx: chunk_builder.command_sender,
             chunk_builder_rx: chunk_builder.data_recv,
+            chunk_builder_pending: chunk_builder.pending,
         }
     }
     /* 
@@ -203,24 +322,36 @@ impl ChunkWorld {
     }
     */
 
-    pub fn update(&mut self, allocators: Arc<MemAllocators>, camera: &Camera) {
+    /// `window` is needed for culling
+    pub fn update(&mut self, allocators: Arc<MemAllocators>, camera: &Camera, window: &WindowState) {
+        /* changes nobody committed make up one edit per frame */
+        self.history.commit("edit");
+        /* and events nobody took are dropped, so they don't pile up */
+        self.events = WorldEvents::default();
+
         let mut existing_chunks = vec![];
         for key in self.chunks.keys() {
             existing_chunks.push(*key);
         }
-        self.chunk_builder_tx.try_send(
+        /* counted before sending, so a builder can't be done with it first */
+        self.chunk_builder_pending.fetch_add(1, Ordering::SeqCst);
+        let sent = self.chunk_builder_tx.try_send(
             ChunkBuilderCommands::Info(*camera, existing_chunks),
-        ).ok();
+        );
+        if sent.is_err() {
+            self.chunk_builder_pending.fetch_sub(1, Ordering::SeqCst);
+        }
 
-        while let Ok(rx) = self.chunk_builder_rx.try_recv() {
-            let (k, chunk, mesh) = rx.chunk;
+        self.receive_built(allocators.clone());
 
-            self.chunks.insert(k, chunk);
-            self.meshes.insert(k, mesh);
-        }
+        self.remesh_dirty(allocators.clone());
 
         self.chunks_to_remove.retain(|k| {
-            self.chunks.remove(k).is_some()
+            let removed = self.chunks.remove(k).is_some();
+            if removed {
+                self.rebuilding.insert(*k);
+            }
+            removed
         });
         self.meshes_to_remove.retain(|k| {
             self.meshes.remove(k).is_some()
@@ -245,13 +376,307 @@ impl ChunkWorld {
 
         for (k, mesh) in &mut self.meshes {
             if let Some(ref mut mesh) = mesh {
-                mesh.visible = ChunkCuller::is_visible(*k, camera);
+                mesh.visible = ChunkCuller::is_visible(*k, camera, window);
+            }
+        }
+    }
+
+    /// Takes in the chunks the builder is done with
+    fn receive_built(&mut self, allocators: Arc<MemAllocators>) {
+        while let Ok(rx) = self.chunk_builder_rx.try_recv() {
+            let (k, chunk, mesh) = rx.chunk;
+
+            if let Some(mesh) = &mesh {
+                self.quad_ebo.reserve(allocators.clone(), mesh.quads());
+            }
+
+            let mut chunk = chunk;
+            if let Some(edits) = self.edits.get(&k) {
+                for (idx, voxel) in edits {
+                    chunk.set(*idx, *voxel);
+                }
+                /* the builder meshed it without them */
+                self.dirty.insert(k);
             }
+
+            /* chunks rebuilt at another lod were already loaded once */
+            if !self.rebuilding.remove(&k) && !self.chunks.contains_key(&k) {
+                self.events.loaded.push(k);
+            }
+            self.chunks.insert(k, chunk);
+            self.meshes.insert(k, mesh);
+
+            voxel_light::seed_chunk(&mut self.chunks, &self.light_sources, k, &mut self.dirty);
+        }
+    }
+
+    /*
+    Waits until the builder is through with every request and takes in what it
+    built. Headless runs call it every step with an `in_order` builder, so
+    what gets loaded depends on the camera only and not on how fast the
+    builder tasks happened to be.
+    */
+    pub async fn wait_for_builder(&mut self, allocators: Arc<MemAllocators>) {
+        /* the builders block when the results channel is full, so keep taking them */
+        while self.chunk_builder_pending.load(Ordering::SeqCst) > 0 || !self.chunk_builder_rx.is_empty() {
+            self.receive_built(allocators.clone());
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Requests waiting for a builder task, and built chunks waiting for `update`
+    pub fn builder_queue(&self) -> (usize, usize) {
+        let commands = self.chunk_builder_tx.max_capacity() - self.chunk_builder_tx.capacity();
+        (commands, self.chunk_builder_rx.len())
+    }
+
+    pub fn chunk(&self, k: &ChunkKey) -> Option<&Chunk> {
+        self.chunks.get(k)
+    }
+
+    pub fn take_events(&mut self) -> WorldEvents {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Runs `f` without its edits showing up in `events.edited`, for the ones
+    /// scripts queued: they would come back to the script's `on_edit`
+    pub fn without_edit_events<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let edited = self.events.edited;
+        let result = f(self);
+        self.events.edited = edited;
+        result
+    }
+
+    /// `None` if the chunk isn't loaded
+    pub fn voxel_at(&self, pos: IVec3) -> Option<Voxel> {
+        let (key, idx) = Chunk::locate_voxel(pos);
+        self.chunks.get(&key).map(|chunk| chunk.get(idx))
+    }
+
+    /// Unloaded chunks count as air
+    pub fn is_solid(&self, pos: IVec3) -> bool {
+        self.voxel_at(pos).map_or(false, |voxel| voxel.id != 0)
+    }
+
+    /// First solid voxel along a ray, see `raycast::raycast`
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_distance: f32) -> Option<RayHit> {
+        raycast::raycast(&|pos| self.is_solid(pos), origin, dir, max_distance)
+    }
+
+    /*
+    Changes a voxel in world space and remembers the change, so it is still
+    there when the chunk gets regenerated for another lod. The chunk and the
+    neighbours sharing the voxel's faces are meshed again at the next
+    `update`, on this thread, instead of going through the builder.
+    The change goes to `history`, to be committed by the caller.
+    Returns false if the chunk isn't loaded.
+    */
+    pub fn set_voxel(&mut self, pos: IVec3, voxel: Voxel) -> bool {
+        let Some(before) = self.voxel_at(pos) else {
+            return false;
+        };
+
+        if before.id != voxel.id {
+            self.write_voxel(pos, voxel);
+            self.history.record(pos, before.id, voxel.id);
+        }
+
+        true
+    }
+
+    /// Reverts the last edit, false if there was none
+    pub fn undo(&mut self) -> bool {
+        self.history.commit("edit");
+        let Some(edit) = self.history.undo() else {
+            return false;
+        };
+
+        for change in edit.changes.iter().rev() {
+            self.write_voxel(change.pos, Voxel { id: change.before });
+        }
+
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.history.commit("edit");
+        let Some(edit) = self.history.redo() else {
+            return false;
+        };
+
+        for change in &edit.changes {
+            self.write_voxel(change.pos, Voxel { id: change.after });
+        }
+
+        true
+    }
+
+    /// Like `set_voxel`, without the history. Chunks that aren't loaded get
+    /// the change when they are
+    fn write_voxel(&mut self, pos: IVec3, voxel: Voxel) {
+        let (key, idx) = Chunk::locate_voxel(pos);
+        self.edits.entry(key).or_default().insert(idx, voxel);
+        self.events.edited = Some(match self.events.edited {
+            Some((min, max)) => (min.min(pos), max.max(pos)),
+            None => (pos, pos),
+        });
+
+        let Some(chunk) = self.chunks.get_mut(&key) else {
+            return;
+        };
+
+        chunk.set(idx, voxel);
+
+        voxel_light::on_voxel_changed(&mut self.chunks, &self.light_sources, pos, &mut self.dirty);
+
+        self.dirty.insert(key);
+        for ofs in [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z] {
+            let (neighbour, _) = Chunk::locate_voxel(pos + ofs);
+            if neighbour != key && self.chunks.contains_key(&neighbour) {
+                self.dirty.insert(neighbour);
+            }
+        }
+    }
+
+    /*
+    Saves every edit and the history to undo them:
+
+        b"CAVE", u32 version
+        u32 chunk count, then per chunk: i32 i, j, k, u32 voxel count,
+            then per voxel: u32 index, u32 id
+        the history, see `EditHistory::write`
+    */
+    pub fn save_edits(&mut self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(b"CAVE")?;
+        history::write_u32(&mut out, 1)?;
+
+        history::write_u32(&mut out, self.edits.len() as u32)?;
+        for (key, voxels) in &self.edits {
+            for v in [key.0, key.1, key.2] {
+                history::write_u32(&mut out, v as i32 as u32)?;
+            }
+            history::write_u32(&mut out, voxels.len() as u32)?;
+            for (idx, voxel) in voxels {
+                history::write_u32(&mut out, *idx as u32)?;
+                history::write_u32(&mut out, voxel.id as u32)?;
+            }
+        }
+
+        self.history.write(&mut out)?;
+        out.flush()
+    }
+
+    /// Replaces the edits and the history with the ones saved at `path`
+    pub fn load_edits(&mut self, path: &Path) -> io::Result<()> {
+        let mut input = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != b"CAVE" || history::read_u32(&mut input)? != 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a saved world"));
+        }
+
+        let mut edits = HashMap::new();
+        for _ in 0..history::read_u32(&mut input)? {
+            let mut key = [0; 3];
+            for v in &mut key {
+                *v = history::read_u32(&mut input)? as i32 as isize;
+            }
+
+            let mut voxels = HashMap::new();
+            for _ in 0..history::read_u32(&mut input)? {
+                let idx = history::read_u32(&mut input)? as usize;
+                let id = history::read_u32(&mut input)? as usize;
+                if idx < CHUNK_VOLUME {
+                    voxels.insert(idx, Voxel { id });
+                }
+            }
+            edits.insert((key[0], key[1], key[2]), voxels);
+        }
+
+        self.history = EditHistory::read(&mut input)?;
+
+        /* loaded chunks with old or new edits get built again, like on a lod change */
+        let touched: Vec<ChunkKey> = self.edits.keys().chain(edits.keys()).copied().collect();
+        self.edits = edits;
+        for key in touched {
+            if self.chunks.contains_key(&key) && !self.chunks_to_remove.contains(&key) {
+                self.chunks_to_remove.push(key);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Places a light source (a torch) at a voxel in world space
+    pub fn place_light(&mut self, pos: IVec3, level: u8) {
+        self.light_sources.insert(pos, level);
+        voxel_light::add_source(&mut self.chunks, pos, level, &mut self.dirty);
+    }
+
+    pub fn remove_light(&mut self, pos: IVec3) {
+        if self.light_sources.remove(&pos).is_some() {
+            voxel_light::remove_light(&mut self.chunks, &self.light_sources, pos, &mut self.dirty);
+        }
+    }
+
+    fn remesh_dirty(&mut self, allocators: Arc<MemAllocators>) {
+        for k in self.dirty.drain() {
+            let Some(chunk) = self.chunks.get(&k) else {
+                continue;
+            };
+
+            let origin = Chunk::get_worldpos(&k).as_ivec3();
+            let chunks = &self.chunks;
+            let mesh = chunk.get_mesh_with_light(allocators.clone(), &|x, y, z| {
+                let pos = origin + ivec3(x as i32, y as i32, z as i32);
+                voxel_light::get_light(chunks, pos)
+            });
+
+            if let Some(mesh) = &mesh {
+                self.quad_ebo.reserve(allocators.clone(), mesh.quads());
+            }
+
+            self.meshes.insert(k, mesh);
         }
     }
 }
 
-pub fn insert_chunkworld_resource(mut commands: Commands, allocators: Arc<MemAllocators>) {
-    let chunk_world = ChunkWorld::new(allocators);
+pub fn insert_chunkworld_resource(mut commands: Commands, allocators: Arc<MemAllocators>, in_order: bool) {
+    let chunk_world = ChunkWorld::new(allocators, in_order);
     commands.insert_resource(chunk_world);
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use glam::{ivec3, vec3};
+
+    use super::{Chunk, CHUNK_SIZE};
+
+    #[test]
+    fn points_just_below_zero_are_in_chunk_minus_one() {
+        assert_eq!(Chunk::get_ijk_chunkspace(vec3(-0.5, -0.5, -0.5)), (-1, -1, -1));
+        assert_eq!(Chunk::get_ijk_chunkspace(vec3(0.5, -0.5, 0.0)), (0, -1, 0));
+        assert_eq!(Chunk::get_ijk_chunkspace(vec3(-(CHUNK_SIZE as f32), 0.0, -(CHUNK_SIZE as f32) - 0.01)), (-1, 0, -2));
+    }
+
+    #[test]
+    fn local_indices_wrap_around_below_zero() {
+        let last = CHUNK_SIZE as i32 - 1;
+
+        assert_eq!(Chunk::locate_voxel(ivec3(-1, 0, 0)), ((-1, 0, 0), Chunk::local_index(ivec3(last, 0, 0))));
+        assert_eq!(Chunk::locate_voxel(ivec3(0, -1, 0)), ((0, -1, 0), Chunk::local_index(ivec3(0, last, 0))));
+        assert_eq!(Chunk::locate_voxel(ivec3(3, 4, -(CHUNK_SIZE as i32) - 2)), ((0, 0, -2), Chunk::local_index(ivec3(3, 4, last - 1))));
+    }
+
+    #[test]
+    fn chunk_corners_round_trip() {
+        for key in [(-1, 0, 2), (0, -3, 0), (5, 5, -5)] {
+            assert_eq!(Chunk::get_ijk_chunkspace(Chunk::get_worldpos(&key)), key);
+        }
+    }
+}